@@ -0,0 +1,259 @@
+//! # Simple Settings Derive
+//! Derive macro implementing `simple_settings::Validate` from per-field constraints.
+//! Use it through the `derive` feature of `simple-settings` rather than directly.
+//!
+//! Fields accept `#[setting(...)]` attributes with the following constraints, any number of which can be combined:
+//!
+//! - `range = 1..=65535`: the value must be contained in the range.
+//! - `non_empty`: the value's `is_empty()` must be false.
+//! - `one_of = ["debug", "info"]`: the value must equal one of the listed values.
+//! - `regex = "^[a-z]+$"`: the string must match the pattern, which is checked at compile time.
+//! - `check = "path::to::function"`: the function receives the field and returns `Result<(), impl Into<String>>`.
+//! - `nested`: the field is validated with its own `Validate` implementation.
+//!
+//! On the struct, `#[setting(check = "path::to::function")]` runs a cross-field check
+//! receiving the whole struct and returning `Result<(), ValidationErrors>`.
+//! Errors name fields by their serialized names, honoring `#[serde(rename = "...")]` on fields
+//! and `#[serde(rename_all = "...")]` on the struct.
+
+extern crate proc_macro;
+
+use {
+    proc_macro::TokenStream,
+    proc_macro2::TokenStream as TokenStream2,
+    quote::{quote, ToTokens},
+    syn::{
+        parenthesized, parse_macro_input, punctuated::Punctuated, token, Attribute, Data,
+        DeriveInput, Expr, ExprArray, ExprRange, LitStr, Member, Path, RangeLimits, Token,
+    },
+};
+
+/// Derive `simple_settings::Validate` from `#[setting(...)]` attributes. See the crate documentation.
+#[proc_macro_derive(Validate, attributes(setting))]
+pub fn derive_validate(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Validate can only be derived for structs",
+            ))
+        }
+    };
+
+    let rename_all = serde_attr(&input.attrs, "rename_all");
+    let mut checks = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(i.into()),
+        };
+        let name = match (serde_attr(&field.attrs, "rename"), &field.ident) {
+            (Some(name), _) => name,
+            (None, Some(ident)) => {
+                let name = ident.to_string().trim_start_matches("r#").to_string();
+                match &rename_all {
+                    Some(rule) => rename_field(rule, &name),
+                    None => name,
+                }
+            }
+            (None, None) => i.to_string(),
+        };
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("setting")) {
+            attr.parse_nested_meta(|meta| {
+                let value = quote!(self.#member);
+                let check = if meta.path.is_ident("range") {
+                    range(&name, &value, meta.value()?.parse()?)
+                } else if meta.path.is_ident("non_empty") {
+                    quote! {
+                        if #value.is_empty() {
+                            errors.add(#name, "must not be empty");
+                        }
+                    }
+                } else if meta.path.is_ident("one_of") {
+                    one_of(&name, &value, meta.value()?.parse()?)
+                } else if meta.path.is_ident("regex") {
+                    regex(&name, &value, meta.value()?.parse()?)?
+                } else if meta.path.is_ident("check") {
+                    let function = meta.value()?.parse::<LitStr>()?.parse::<Path>()?;
+                    quote! {
+                        if let ::std::result::Result::Err(message) = #function(&#value) {
+                            errors.add(#name, message);
+                        }
+                    }
+                } else if meta.path.is_ident("nested") {
+                    quote! {
+                        errors.nest(#name, ::simple_settings::Validate::validate(&#value));
+                    }
+                } else {
+                    return Err(meta.error(
+                        "unknown setting constraint, expected one of `range`, `non_empty`, `one_of`, `regex`, `check` or `nested`",
+                    ));
+                };
+                checks.push(check);
+                Ok(())
+            })?;
+        }
+    }
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("setting")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("check") {
+                let function = meta.value()?.parse::<LitStr>()?.parse::<Path>()?;
+                checks.push(quote! {
+                    errors.nest("", #function(self));
+                });
+                Ok(())
+            } else {
+                Err(meta.error("only `check` is supported on structs"))
+            }
+        })?;
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::simple_settings::Validate for #ident #ty_generics #where_clause {
+            fn validate(&self) -> ::std::result::Result<(), ::simple_settings::ValidationErrors> {
+                let mut errors = ::simple_settings::ValidationErrors::new();
+                #(#checks)*
+                errors.into_result()
+            }
+        }
+    })
+}
+
+/// Value of the serde attribute `key`, given as `key = "..."` or `key(serialize = "...")`.
+fn serde_attr(attrs: &[Attribute], key: &str) -> Option<String> {
+    let mut found = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        // Other serde attributes are none of our business, so parse errors are ignored.
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) && meta.input.peek(Token![=]) {
+                found = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident(key) {
+                meta.parse_nested_meta(|inner| {
+                    let value = inner.value()?.parse::<LitStr>()?.value();
+                    if inner.path.is_ident("serialize") {
+                        found = Some(value);
+                    }
+                    Ok(())
+                })?;
+            } else if meta.input.peek(Token![=]) {
+                meta.value()?.parse::<Expr>()?;
+            } else if meta.input.peek(token::Paren) {
+                let content;
+                parenthesized!(content in meta.input);
+                content.parse::<TokenStream2>()?;
+            }
+            Ok(())
+        });
+    }
+    found
+}
+
+/// Apply a serde `rename_all` rule to a field name.
+fn rename_field(rule: &str, field: &str) -> String {
+    let pascal = || {
+        let mut out = String::new();
+        let mut capitalize = true;
+        for c in field.chars() {
+            if c == '_' {
+                capitalize = true;
+            } else if capitalize {
+                out.push(c.to_ascii_uppercase());
+                capitalize = false;
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    };
+    match rule {
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+                None => pascal,
+            }
+        }
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.to_ascii_uppercase().replace('_', "-"),
+        _ => field.to_string(),
+    }
+}
+
+/// Source text of an expression for error messages.
+fn text(expr: &Expr) -> String {
+    expr.to_token_stream().to_string().replace("- ", "-")
+}
+
+fn range(name: &str, value: &TokenStream2, range: ExprRange) -> TokenStream2 {
+    let closed = matches!(range.limits, RangeLimits::Closed(_));
+    let message = match (&range.start, &range.end) {
+        (Some(start), Some(end)) if closed => {
+            format!("must be between {} and {}", text(start), text(end))
+        }
+        (Some(start), Some(end)) => {
+            format!(
+                "must be at least {} and less than {}",
+                text(start),
+                text(end)
+            )
+        }
+        (Some(start), None) => format!("must be at least {}", text(start)),
+        (None, Some(end)) if closed => format!("must be at most {}", text(end)),
+        (None, Some(end)) => format!("must be less than {}", text(end)),
+        (None, None) => return TokenStream2::new(),
+    };
+    quote! {
+        if !(#range).contains(&#value) {
+            errors.add(#name, #message);
+        }
+    }
+}
+
+fn one_of(name: &str, value: &TokenStream2, allowed: ExprArray) -> TokenStream2 {
+    let elems: &Punctuated<Expr, Token![,]> = &allowed.elems;
+    let message = format!(
+        "must be one of {}",
+        elems.iter().map(text).collect::<Vec<_>>().join(", ")
+    );
+    quote! {
+        if ![#elems].iter().any(|allowed| #value == *allowed) {
+            errors.add(#name, #message);
+        }
+    }
+}
+
+fn regex(name: &str, value: &TokenStream2, pattern: LitStr) -> syn::Result<TokenStream2> {
+    // Compiled rather than only parsed, so that patterns over the size limit are rejected here too.
+    if let Err(e) = regex::Regex::new(&pattern.value()) {
+        return Err(syn::Error::new_spanned(
+            &pattern,
+            format!("invalid regex: {}", e),
+        ));
+    }
+    let message = format!("must match `{}`", pattern.value());
+    Ok(quote! {
+        {
+            static REGEX: ::std::sync::OnceLock<::simple_settings::__private::Regex> =
+                ::std::sync::OnceLock::new();
+            let regex = REGEX.get_or_init(|| {
+                ::simple_settings::__private::Regex::new(#pattern).expect("pattern checked at compile time")
+            });
+            if !regex.is_match(::std::convert::AsRef::<str>::as_ref(&#value)) {
+                errors.add(#name, #message);
+            }
+        }
+    })
+}
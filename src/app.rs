@@ -0,0 +1,151 @@
+//! Platform-standard locations for application settings.
+
+use {
+    crate::{Auto, Error, Settings},
+    serde::{de::DeserializeOwned, Serialize},
+    std::{
+        env,
+        ops::{Deref, DerefMut},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Per-user directories of an application.
+///
+/// On Unix these follow the [XDG base directory specification](https://specifications.freedesktop.org/basedir-spec/latest/),
+/// honoring `XDG_CONFIG_HOME`, `XDG_STATE_HOME`, `XDG_CACHE_HOME` and `XDG_DATA_HOME` and otherwise deriving them from `HOME`.
+/// On Windows `APPDATA` and `LOCALAPPDATA` are used.
+/// Directories that cannot be resolved from these variables are unknown, since guessing a shared location
+/// such as the temporary directory would let other local users tamper with the settings.
+/// Each directory is resolved on its own, so an explicitly set one is known even without a home directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppDirs {
+    config: Option<PathBuf>,
+    state: Option<PathBuf>,
+    cache: Option<PathBuf>,
+    data: Option<PathBuf>,
+}
+
+/// Read an environment variable holding an absolute path. Relative paths are ignored as required by the XDG specification.
+fn env_dir(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+#[cfg(not(windows))]
+fn home_dir() -> Option<PathBuf> {
+    env_dir("HOME")
+}
+
+#[cfg(windows)]
+fn home_dir() -> Option<PathBuf> {
+    env_dir("USERPROFILE")
+}
+
+impl AppDirs {
+    /// Resolve directories for application `app` published by `org`.
+    /// The organization is only part of the path on Windows.
+    #[cfg(not(windows))]
+    pub fn new(org: &str, app: &str) -> Self {
+        let _ = org;
+        let home = home_dir();
+        let resolve = |var: &str, fallback: &str| {
+            env_dir(var)
+                .or_else(|| home.as_ref().map(|home| home.join(fallback)))
+                .map(|dir| dir.join(app))
+        };
+        Self {
+            config: resolve("XDG_CONFIG_HOME", ".config"),
+            state: resolve("XDG_STATE_HOME", ".local/state"),
+            cache: resolve("XDG_CACHE_HOME", ".cache"),
+            data: resolve("XDG_DATA_HOME", ".local/share"),
+        }
+    }
+
+    /// Resolve directories for application `app` published by `org`.
+    #[cfg(windows)]
+    pub fn new(org: &str, app: &str) -> Self {
+        let resolve = |var: &str| {
+            env_dir(var)
+                .or_else(|| home_dir().map(|home| home.join("AppData")))
+                .map(|dir| dir.join(org).join(app))
+        };
+        let roaming = resolve("APPDATA");
+        let local = resolve("LOCALAPPDATA");
+        Self {
+            config: roaming.as_ref().map(|dir| dir.join("config")),
+            state: local.as_ref().map(|dir| dir.join("state")),
+            cache: local.as_ref().map(|dir| dir.join("cache")),
+            data: roaming.as_ref().map(|dir| dir.join("data")),
+        }
+    }
+
+    /// Directory for user configuration, if known.
+    pub fn config_dir(&self) -> Option<&Path> {
+        self.config.as_deref()
+    }
+
+    /// Directory for state that should persist between runs but is not configuration, such as history, if known.
+    pub fn state_dir(&self) -> Option<&Path> {
+        self.state.as_deref()
+    }
+
+    /// Directory for non-essential cached data, if known.
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache.as_deref()
+    }
+
+    /// Directory for user data files, if known.
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.data.as_deref()
+    }
+}
+
+/// Settings stored in an application's standard configuration directory.
+///
+/// Dereferences to the underlying [`Settings`].
+pub struct AppSettings<T, F = Auto> {
+    settings: Settings<T, F>,
+    dirs: AppDirs,
+}
+
+impl<T> AppSettings<T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    /// Open the settings file `file` in the configuration directory of application `app` published by `org`.
+    /// The file and its directory are created with default settings if missing.
+    /// Fails with [`Error::NoHomeDir`] if the configuration directory is unknown.
+    pub fn open(org: &str, app: &str, file: impl AsRef<Path>) -> Result<Self, Error> {
+        let dirs = AppDirs::new(org, app);
+        let config = dirs.config_dir().ok_or(Error::NoHomeDir)?;
+        let (settings, _) = Settings::load_or_default(config.join(file))?;
+        Ok(Self { settings, dirs })
+    }
+}
+
+impl<T, F> AppSettings<T, F> {
+    /// Directories of the application.
+    pub fn dirs(&self) -> &AppDirs {
+        &self.dirs
+    }
+
+    /// Unwrap the underlying settings.
+    pub fn into_inner(self) -> Settings<T, F> {
+        self.settings
+    }
+}
+
+impl<T, F> Deref for AppSettings<T, F> {
+    type Target = Settings<T, F>;
+    fn deref(&self) -> &Self::Target {
+        &self.settings
+    }
+}
+
+impl<T, F> DerefMut for AppSettings<T, F> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.settings
+    }
+}
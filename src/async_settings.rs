@@ -0,0 +1,265 @@
+//! Settings for async code running on tokio.
+
+use {
+    crate::{
+        shared::PendingWrite, Auto, Error, Format, Origin, Reloader, Settings, SettingsGuard,
+        SharedSettings, SharedSettingsGuard, Subscription,
+    },
+    serde::{de::DeserializeOwned, Serialize},
+    std::{
+        future::Future,
+        io,
+        ops::{Deref, DerefMut},
+        panic,
+        path::{Path, PathBuf},
+        thread,
+    },
+    tokio::{runtime::Handle, task},
+};
+
+/// Run blocking file I/O on the runtime's blocking pool. `path` is reported if the task is cancelled.
+async fn blocking<R>(
+    path: &Path,
+    f: impl FnOnce() -> Result<R, Error> + Send + 'static,
+) -> Result<R, Error>
+where
+    R: Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(e) => match e.try_into_panic() {
+            Ok(payload) => panic::resume_unwind(payload),
+            Err(e) => Err(Error::io(path, io::Error::other(e))),
+        },
+    }
+}
+
+/// Settings handle for async code. Loading and saving run on tokio's blocking pool instead of the executor thread.
+///
+/// The handle is cloneable and shares the settings like [`SharedSettings`].
+/// Guards lock the data synchronously and must not be held across an `.await`.
+pub struct AsyncSettings<T, F = Auto> {
+    shared: SharedSettings<T, F>,
+    path: PathBuf,
+}
+
+impl<T, F> Clone for AsyncSettings<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<T, F> From<Settings<T, F>> for AsyncSettings<T, F> {
+    fn from(settings: Settings<T, F>) -> Self {
+        Self {
+            path: settings.path.clone(),
+            shared: settings.into(),
+        }
+    }
+}
+
+impl<T> AsyncSettings<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Create configuration and store it to disk. See [`Settings::new`].
+    pub async fn new(path: impl AsRef<Path>, data: T) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        blocking(&path.clone(), move || Settings::new(path, data))
+            .await
+            .map(Self::from)
+    }
+
+    /// Load configuration from disk. Returns `None` if the file does not exist. See [`Settings::load`].
+    pub async fn load(path: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = path.as_ref().to_path_buf();
+        blocking(&path.clone(), move || Settings::load(path))
+            .await
+            .map(|settings| settings.map(Self::from))
+    }
+
+    /// Load configuration from disk, or create it from `T::default()` if the file does not exist.
+    pub async fn load_or_default(path: impl AsRef<Path>) -> Result<(Self, Origin), Error>
+    where
+        T: Default,
+    {
+        let path = path.as_ref().to_path_buf();
+        blocking(&path.clone(), move || Settings::load_or_default(path))
+            .await
+            .map(|(settings, origin)| (settings.into(), origin))
+    }
+}
+
+impl<T, F> AsyncSettings<T, F>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: Format + Send + Sync + 'static,
+{
+    /// Lock configuration for read access.
+    pub fn read(&self) -> SettingsGuard<'_, T> {
+        self.shared.read()
+    }
+
+    /// Lock configuration for mutable access.
+    /// Use [`AsyncSettingsGuard::commit`] to save; a guard dropped without it saves in the background.
+    pub fn write(&self) -> AsyncSettingsGuard<'_, T, F> {
+        AsyncSettingsGuard {
+            guard: self.shared.write(),
+        }
+    }
+
+    /// Modify configuration with `f` and save it to disk, returning the closure's result.
+    pub async fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, Error> {
+        let (r, commit) = {
+            let mut guard = self.write();
+            let r = f(&mut guard);
+            (r, guard.commit())
+        };
+        commit.await?;
+        Ok(r)
+    }
+
+    /// Reload the file if it was modified since it was last read or written. See [`Settings::reload_if_changed`].
+    pub async fn reload_if_changed(&self) -> Result<bool, Error> {
+        let shared = self.shared.clone();
+        blocking(&self.path, move || shared.reload_if_changed()).await
+    }
+
+    /// Reload the file in the background whenever another program changes it. See [`SharedSettings::reload_on_change`].
+    pub fn reload_on_change(&self) -> Reloader {
+        self.shared.reload_on_change()
+    }
+
+    /// Call `callback` with the old and new data after every change. See [`Settings::subscribe`].
+    pub fn subscribe(&self, callback: impl Fn(&T, &T) + Send + Sync + 'static) -> Subscription
+    where
+        T: Clone,
+    {
+        self.shared.subscribe(callback)
+    }
+
+    /// Call `callback` after changes to the value at a dotted key path. See [`Settings::subscribe_path`].
+    pub fn subscribe_path(
+        &self,
+        path: impl Into<String>,
+        callback: impl Fn(&T, &T) + Send + Sync + 'static,
+    ) -> Subscription
+    where
+        T: Clone,
+    {
+        self.shared.subscribe_path(path, callback)
+    }
+
+    /// Call `callback` after changes to the field picked by `select`. See [`Settings::subscribe_field`].
+    pub fn subscribe_field<V>(
+        &self,
+        select: impl Fn(&T) -> &V + Send + Sync + 'static,
+        callback: impl Fn(&V, &V) + Send + Sync + 'static,
+    ) -> Subscription
+    where
+        T: Clone,
+        V: PartialEq + ?Sized,
+    {
+        self.shared.subscribe_field(select, callback)
+    }
+
+    /// Cancel a subscription, returning whether it was active.
+    pub fn unsubscribe(&self, subscription: Subscription) -> bool {
+        self.shared.unsubscribe(subscription)
+    }
+}
+
+/// Guard for mutable access to [`AsyncSettings`].
+///
+/// Drop cannot wait for I/O, so a guard dropped without [`AsyncSettingsGuard::commit`] saves on the blocking pool
+/// in the background and reports errors to the hook set with [`Settings::set_error_hook`].
+pub struct AsyncSettingsGuard<'a, T, F = Auto>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: Format + Send + Sync + 'static,
+{
+    guard: SharedSettingsGuard<'a, T, F>,
+}
+
+impl<'a, T, F> AsyncSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: Format + Send + Sync + 'static,
+{
+    /// Save the data to disk, consuming the guard.
+    ///
+    /// The data is serialized and unlocked right away, so that the returned future holds no lock
+    /// and can be spawned on a multi-threaded runtime.
+    pub fn commit(mut self) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let write = self.guard.detach();
+        async move {
+            match write? {
+                Some(write) => {
+                    let path = write.path.clone();
+                    blocking(&path, move || write.write()).await
+                }
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+impl<'a, T, F> Deref for AsyncSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: Format + Send + Sync + 'static,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T, F> DerefMut for AsyncSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: Format + Send + Sync + 'static,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, T, F> Drop for AsyncSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: Format + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // The inner guard discards the changes when it is dropped.
+            return;
+        }
+        let write = match self.guard.detach() {
+            Ok(Some(write)) => write,
+            Ok(None) => return,
+            Err(e) => return self.guard.shared.report(&e),
+        };
+        match Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || save(write));
+            }
+            Err(_) => save(write),
+        }
+    }
+}
+
+/// Write a change, reporting failures to the error hook.
+fn save<T, F>(write: PendingWrite<T, F>)
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    let shared = write.shared.clone();
+    if let Err(e) = write.write() {
+        shared.report(&e);
+    }
+}
@@ -0,0 +1,257 @@
+//! Copies of previous settings files kept on every save, and recovery from corrupted files.
+
+use {
+    crate::Error,
+    std::{
+        ffi::OsString,
+        fmt, fs, io,
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Naming {
+    /// `<file>.1` for the newest backup up to `<file>.<keep>` next to the file.
+    Numbered,
+    /// `<file>.<timestamp>` in the given directory.
+    Timestamped(PathBuf),
+}
+
+/// How many previous versions of a settings file to keep and where.
+///
+/// Before each save the current file is copied to a backup and the oldest backups beyond the limit are removed.
+/// Saves that do not change the file take no backup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Backups {
+    naming: Naming,
+    keep: usize,
+}
+
+impl Backups {
+    /// Keep `keep` previous versions next to the file, from `<file>.1` for the newest to `<file>.<keep>` for the oldest.
+    pub fn numbered(keep: usize) -> Self {
+        Self {
+            naming: Naming::Numbered,
+            keep,
+        }
+    }
+
+    /// Keep `keep` previous versions in `dir` as `<file>.<timestamp>`, with UTC timestamps such as `20240131T235959.123Z`.
+    /// Later backups taken within the same millisecond are named `<file>.<timestamp>-1`, `<file>.<timestamp>-2` and so on.
+    pub fn timestamped(dir: impl AsRef<Path>, keep: usize) -> Self {
+        Self {
+            naming: Naming::Timestamped(dir.as_ref().to_path_buf()),
+            keep,
+        }
+    }
+
+    /// Copy the file at `path` to a new backup, if it exists, and remove backups beyond the limit.
+    pub(crate) fn take(&self, path: &Path) -> Result<(), Error> {
+        if self.keep == 0 || !path.exists() {
+            return Ok(());
+        }
+        let backup = match &self.naming {
+            Naming::Numbered => {
+                for (n, old) in self.list(path)?.into_iter().enumerate().rev() {
+                    let n = n + 1;
+                    if n >= self.keep {
+                        fs::remove_file(&old).map_err(|e| Error::io(&old, e))?;
+                    } else {
+                        let new = numbered(path, n + 1);
+                        fs::rename(&old, &new).map_err(|e| Error::io(&old, e))?;
+                    }
+                }
+                numbered(path, 1)
+            }
+            Naming::Timestamped(dir) => {
+                fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+                let backups = self.entries(path)?;
+                for (_, _, old) in backups.iter().skip(self.keep - 1) {
+                    fs::remove_file(old).map_err(|e| Error::io(old, e))?;
+                }
+                let time = timestamp(SystemTime::now());
+                // Count on from the newest backup if it was taken within the same millisecond, so that the new one sorts first.
+                let n = match backups.first() {
+                    Some((newest, n, _)) if *newest == time => n + 1,
+                    _ => 0,
+                };
+                reserve(dir, path, &time, n)?
+            }
+        };
+        fs::copy(path, &backup).map_err(|e| Error::io(&backup, e))?;
+        Ok(())
+    }
+
+    /// Backups of the file at `path`, newest first.
+    pub(crate) fn list(&self, path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let backups = self.entries(path)?;
+        Ok(backups.into_iter().map(|(_, _, path)| path).collect())
+    }
+
+    /// Timestamp, if any, number or counter and path of each backup of the file at `path`, newest first.
+    fn entries(&self, path: &Path) -> Result<Vec<(String, u64, PathBuf)>, Error> {
+        let dir = match &self.naming {
+            Naming::Numbered => match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            },
+            Naming::Timestamped(dir) => dir,
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Error::io(dir, e)),
+        };
+        let prefix = format!(
+            "{}.",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+
+        let mut backups = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| Error::io(dir, e))?;
+            let name = entry.file_name();
+            let suffix = match name.to_str().and_then(|name| name.strip_prefix(&prefix)) {
+                Some(suffix) => suffix,
+                None => continue,
+            };
+            let (time, n) = match &self.naming {
+                Naming::Numbered => match number(suffix) {
+                    Some(n) => ("", n),
+                    None => continue,
+                },
+                Naming::Timestamped(_) => match suffix.split_once('-') {
+                    None if is_timestamp(suffix) => (suffix, 0),
+                    Some((time, n)) if is_timestamp(time) => match number(n) {
+                        Some(n) => (time, n),
+                        None => continue,
+                    },
+                    _ => continue,
+                },
+            };
+            backups.push((time.to_string(), n, entry.path()));
+        }
+        // Numbered backups grow older with the number, timestamped ones with the timestamp and then the counter.
+        backups.sort_by(|a, b| match self.naming {
+            Naming::Numbered => a.1.cmp(&b.1),
+            Naming::Timestamped(_) => (&b.0, b.1).cmp(&(&a.0, a.1)),
+        });
+        Ok(backups)
+    }
+}
+
+/// Create an empty backup of `path` in `dir` named after `time` and counter `n`, counting on while that name is taken.
+fn reserve(dir: &Path, path: &Path, time: &str, mut n: u64) -> Result<PathBuf, Error> {
+    loop {
+        let mut name = OsString::from(path.file_name().unwrap_or_default());
+        name.push(".");
+        name.push(time);
+        if n > 0 {
+            name.push(format!("-{}", n));
+        }
+        let backup = dir.join(name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
+        {
+            Ok(_) => return Ok(backup),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(Error::io(&backup, e)),
+        }
+    }
+}
+
+/// Parse a positive backup number, without a sign.
+fn number(s: &str) -> Option<u64> {
+    match s.parse::<u64>() {
+        Ok(n) if n > 0 && !s.starts_with('+') => Some(n),
+        _ => None,
+    }
+}
+
+/// Report of a settings file that could not be parsed and was replaced, returned by [`Settings::load_or_recover`](crate::Settings::load_or_recover).
+#[derive(Debug)]
+pub struct Recovery {
+    /// Why the file was rejected.
+    pub error: Error,
+    /// Where the rejected file was moved.
+    pub quarantined: PathBuf,
+    /// Backup the settings were restored from, or `None` if they were reset to defaults.
+    pub restored_from: Option<PathBuf>,
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}; the file was moved to {} and ",
+            self.error,
+            self.quarantined.display()
+        )?;
+        match &self.restored_from {
+            Some(backup) => write!(f, "settings were restored from {}", backup.display()),
+            None => write!(f, "settings were reset to defaults"),
+        }
+    }
+}
+
+/// Move the file at `path` out of the way as `<file>.corrupt-<timestamp>`, returning its new path.
+pub(crate) fn quarantine(path: &Path) -> Result<PathBuf, Error> {
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(".corrupt-");
+    name.push(timestamp(SystemTime::now()));
+    let quarantined = path.with_file_name(name);
+    fs::rename(path, &quarantined).map_err(|e| Error::io(path, e))?;
+    Ok(quarantined)
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(format!(".{}", n));
+    path.with_file_name(name)
+}
+
+/// Format `time` as a compact UTC timestamp that sorts chronologically, such as `20240131T235959.123Z`.
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        since.subsec_millis()
+    )
+}
+
+fn is_timestamp(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 20
+        && b[8] == b'T'
+        && b[15] == b'.'
+        && b[19] == b'Z'
+        && b.iter()
+            .enumerate()
+            .all(|(i, c)| matches!(i, 8 | 15 | 19) || c.is_ascii_digit())
+}
+
+/// Convert days since the Unix epoch into a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
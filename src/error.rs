@@ -0,0 +1,135 @@
+use {
+    crate::{
+        format::{DecodeError, EncodeError},
+        ValidationErrors,
+    },
+    std::{
+        error::Error as StdError,
+        fmt, io,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Errors that can occur while loading or persisting settings.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing the settings file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// The settings file could not be parsed into the settings type.
+    Deserialize {
+        path: PathBuf,
+        /// One-based line and column of the error, if known.
+        position: Option<(usize, usize)>,
+        /// Dotted path to the offending key, if known.
+        key: Option<String>,
+        message: String,
+    },
+    /// Settings could not be serialized.
+    Serialize(EncodeError),
+    /// Migrating the settings document to the current schema version failed.
+    Migration {
+        path: PathBuf,
+        /// Version the document was being migrated from, if known.
+        version: Option<u32>,
+        message: String,
+    },
+    /// An override could not be applied to the key at dotted path `key`.
+    Override { key: String, message: String },
+    /// Settings were not saved to `path` because a panic left them half-modified.
+    Poisoned { path: PathBuf },
+    /// The settings file at `path` is locked by another process.
+    Lock { path: PathBuf },
+    /// The settings file at `path` was changed by another program since it was last read.
+    /// Reload it and apply the changes again, or enable locking to keep processes from racing.
+    Conflict { path: PathBuf },
+    /// The settings format could not be determined from the file name.
+    UnknownFormat { path: PathBuf },
+    /// Standard directories could not be resolved because the home directory is unknown.
+    NoHomeDir,
+    /// Settings were rejected as invalid by validation.
+    Validation(ValidationErrors),
+}
+
+impl Error {
+    pub(crate) fn io(path: &Path, source: io::Error) -> Self {
+        Self::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub(crate) fn deserialize(path: &Path, e: DecodeError) -> Self {
+        Self::Deserialize {
+            path: path.to_path_buf(),
+            position: e.position,
+            key: e.key,
+            message: e.message,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Self::Deserialize { path, message, .. } => {
+                write!(f, "failed to parse {}: {}", path.display(), message)
+            }
+            Self::Serialize(e) => write!(f, "failed to serialize settings: {}", e),
+            Self::Migration {
+                path,
+                version,
+                message,
+            } => {
+                write!(f, "failed to migrate {}", path.display())?;
+                if let Some(version) = version {
+                    write!(f, " from schema version {}", version)?;
+                }
+                write!(f, ": {}", message)
+            }
+            Self::Override { key, message } => {
+                write!(f, "invalid override for `{}`: {}", key, message)
+            }
+            Self::Poisoned { path } => write!(
+                f,
+                "refusing to save {}: settings were left inconsistent by a panic",
+                path.display()
+            ),
+            Self::Lock { path } => write!(f, "{} is locked by another process", path.display()),
+            Self::Conflict { path } => write!(
+                f,
+                "{} was changed by another program since it was read",
+                path.display()
+            ),
+            Self::UnknownFormat { path } => write!(
+                f,
+                "cannot determine settings format of {}: unknown or disabled file extension",
+                path.display()
+            ),
+            Self::NoHomeDir => write!(
+                f,
+                "cannot locate settings directory: the home directory is unknown"
+            ),
+            Self::Validation(errors) => write!(f, "invalid settings: {}", errors),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Serialize(e) => Some(e.as_ref()),
+            Self::Deserialize { .. }
+            | Self::Migration { .. }
+            | Self::Override { .. }
+            | Self::Poisoned { .. }
+            | Self::Lock { .. }
+            | Self::Conflict { .. }
+            | Self::UnknownFormat { .. }
+            | Self::NoHomeDir
+            | Self::Validation(_) => None,
+        }
+    }
+}
@@ -0,0 +1,571 @@
+//! Serialization formats for settings files.
+
+use {
+    crate::Error,
+    serde::{
+        de::{DeserializeOwned, IgnoredAny},
+        Serialize,
+    },
+    std::{error::Error as StdError, fmt, path::Path},
+};
+
+/// Error produced by [`Format::encode`].
+pub type EncodeError = Box<dyn StdError + Send + Sync>;
+
+/// Error produced by [`Format::decode`].
+#[derive(Debug)]
+pub struct DecodeError {
+    /// One-based line and column of the error, if known.
+    pub position: Option<(usize, usize)>,
+    /// Dotted path to the offending key, if known.
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl DecodeError {
+    /// Create an error without location information.
+    pub fn new(message: impl fmt::Display) -> Self {
+        Self {
+            position: None,
+            key: None,
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn from_path_error<E: fmt::Display>(
+        e: serde_path_to_error::Error<E>,
+        position: impl FnOnce(&E) -> Option<(usize, usize)>,
+    ) -> Self {
+        let key = Some(e.path().to_string()).filter(|key| key != ".");
+        let inner = e.into_inner();
+        Self {
+            position: position(&inner),
+            key,
+            message: inner.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for DecodeError {}
+
+/// File format used to store settings on disk.
+pub trait Format {
+    /// Serialize `value` into file contents.
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize;
+
+    /// Deserialize file contents.
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned;
+
+    /// Serialize `value` as an update of the existing file contents `previous`, keeping its formatting where possible.
+    /// By default `previous` is ignored.
+    fn encode_update<T>(&self, value: &T, previous: &[u8]) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        let _ = previous;
+        self.encode(value)
+    }
+}
+
+fn from_utf8(bytes: &[u8]) -> Result<&str, DecodeError> {
+    std::str::from_utf8(bytes).map_err(DecodeError::new)
+}
+
+/// [TOML](https://toml.io) format.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Toml;
+
+impl Format for Toml {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        Ok(toml::to_vec(value)?)
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        serde_path_to_error::deserialize(&mut toml::Deserializer::new(from_utf8(bytes)?)).map_err(
+            |e| {
+                DecodeError::from_path_error(e, |e| {
+                    e.line_col().map(|(line, col)| (line + 1, col + 1))
+                })
+            },
+        )
+    }
+
+    /// Patches only changed keys into `previous`, keeping comments, key order and blank lines.
+    #[cfg(feature = "preserve")]
+    fn encode_update<T>(&self, value: &T, previous: &[u8]) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        let new = match toml::Value::try_from(value)? {
+            toml::Value::Table(table) => table,
+            _ => return self.encode(value),
+        };
+        match std::str::from_utf8(previous)
+            .ok()
+            .and_then(|previous| crate::preserve::update(previous, &new))
+        {
+            Some(updated) => Ok(updated.into_bytes()),
+            None => self.encode(value),
+        }
+    }
+}
+
+/// [JSON](https://www.json.org) format.
+#[cfg(feature = "json")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Format for Json {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        Ok(serde_json::to_vec_pretty(value)?)
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        let mut de = serde_json::Deserializer::from_slice(bytes);
+        let value = serde_path_to_error::deserialize(&mut de)
+            .map_err(|e| DecodeError::from_path_error(e, |e| Some((e.line(), e.column()))))?;
+        de.end().map_err(|e| DecodeError {
+            position: Some((e.line(), e.column())),
+            key: None,
+            message: e.to_string(),
+        })?;
+        Ok(value)
+    }
+}
+
+/// [YAML](https://yaml.org) format.
+#[cfg(feature = "yaml")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Yaml;
+
+#[cfg(feature = "yaml")]
+impl Format for Yaml {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        Ok(serde_yaml::to_string(value)?.into_bytes())
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        serde_path_to_error::deserialize(serde_yaml::Deserializer::from_slice(bytes)).map_err(|e| {
+            DecodeError::from_path_error(e, |e| {
+                e.location()
+                    .map(|location| (location.line(), location.column()))
+            })
+        })
+    }
+}
+
+/// [RON](https://github.com/ron-rs/ron) format.
+#[cfg(feature = "ron")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ron;
+
+#[cfg(feature = "ron")]
+impl Format for Ron {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        Ok(ron::ser::to_string_pretty(value, Default::default())?.into_bytes())
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        let spanned = |e: ron::error::SpannedError| DecodeError {
+            position: Some((e.position.line, e.position.col)),
+            key: None,
+            message: e.code.to_string(),
+        };
+        let mut de = ron::Deserializer::from_bytes(bytes).map_err(spanned)?;
+        let value = match serde_path_to_error::deserialize(&mut de) {
+            Ok(value) => value,
+            Err(e) => {
+                let key = Some(e.path().to_string()).filter(|key| key != ".");
+                return Err(DecodeError {
+                    key,
+                    ..spanned(de.span_error(e.into_inner()))
+                });
+            }
+        };
+        de.end().map_err(|e| spanned(de.span_error(e)))?;
+        Ok(value)
+    }
+}
+
+/// [JSON5](https://json5.org) format.
+#[cfg(feature = "json5")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json5;
+
+#[cfg(feature = "json5")]
+impl Format for Json5 {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        Ok(json5::to_string(value)?.into_bytes())
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        let position = |e: &json5::Error| match e {
+            json5::Error::Message { location, .. } => location.as_ref().map(|l| (l.line, l.column)),
+        };
+        let mut de = json5::Deserializer::from_str(from_utf8(bytes)?).map_err(|e| DecodeError {
+            position: position(&e),
+            key: None,
+            message: e.to_string(),
+        })?;
+        serde_path_to_error::deserialize(&mut de)
+            .map_err(|e| DecodeError::from_path_error(e, position))
+    }
+}
+
+/// Header prepended to binary formats so that files written by another format or schema are rejected.
+///
+/// Layout: magic bytes, format tag byte, little-endian `u32` schema version.
+#[cfg(any(feature = "cbor", feature = "msgpack", feature = "bincode"))]
+mod header {
+    use super::DecodeError;
+
+    pub const MAGIC: &[u8; 4] = b"SSET";
+    pub const LEN: usize = MAGIC.len() + 1 + 4;
+
+    pub const CBOR: u8 = 1;
+    pub const MSGPACK: u8 = 2;
+    pub const BINCODE: u8 = 3;
+
+    fn name(tag: u8) -> String {
+        match tag {
+            CBOR => "CBOR".into(),
+            MSGPACK => "MessagePack".into(),
+            BINCODE => "bincode".into(),
+            other => format!("unknown format {}", other),
+        }
+    }
+
+    /// Format tag of `bytes`, if they start with a header.
+    pub fn tag(bytes: &[u8]) -> Option<u8> {
+        if bytes.len() >= LEN && bytes.starts_with(MAGIC) {
+            Some(bytes[MAGIC.len()])
+        } else {
+            None
+        }
+    }
+
+    pub fn write(tag: u8, schema: u32) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(tag);
+        out.extend_from_slice(&schema.to_le_bytes());
+        out
+    }
+
+    /// Check the header and return the payload following it.
+    pub fn strip(
+        bytes: &[u8],
+        expected_tag: u8,
+        expected_schema: u32,
+    ) -> Result<&[u8], DecodeError> {
+        let tag = tag(bytes).ok_or_else(|| DecodeError::new("missing settings file header"))?;
+        if tag != expected_tag {
+            return Err(DecodeError::new(format!(
+                "file was written as {}, expected {}",
+                name(tag),
+                name(expected_tag)
+            )));
+        }
+        let mut schema = [0; 4];
+        schema.copy_from_slice(&bytes[MAGIC.len() + 1..LEN]);
+        let schema = u32::from_le_bytes(schema);
+        if schema != expected_schema {
+            return Err(DecodeError::new(format!(
+                "file has schema version {}, expected {}",
+                schema, expected_schema
+            )));
+        }
+        Ok(&bytes[LEN..])
+    }
+}
+
+/// [CBOR](https://cbor.io) binary format.
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbor {
+    /// Schema version stored in the file header. Files with a different version are rejected.
+    pub schema: u32,
+}
+
+#[cfg(feature = "cbor")]
+impl Format for Cbor {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        let mut out = header::write(header::CBOR, self.schema);
+        ciborium::ser::into_writer(value, &mut out)?;
+        Ok(out)
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        let payload = header::strip(bytes, header::CBOR, self.schema)?;
+        ciborium::de::from_reader(payload).map_err(DecodeError::new)
+    }
+}
+
+/// [MessagePack](https://msgpack.org) binary format.
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePack {
+    /// Schema version stored in the file header. Files with a different version are rejected.
+    pub schema: u32,
+}
+
+#[cfg(feature = "msgpack")]
+impl Format for MessagePack {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        let mut out = header::write(header::MSGPACK, self.schema);
+        value.serialize(&mut rmp_serde::Serializer::new(&mut out).with_struct_map())?;
+        Ok(out)
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        let payload = header::strip(bytes, header::MSGPACK, self.schema)?;
+        serde_path_to_error::deserialize(&mut rmp_serde::Deserializer::new(payload))
+            .map_err(|e| DecodeError::from_path_error(e, |_| None))
+    }
+}
+
+/// [bincode](https://github.com/bincode-org/bincode) binary format.
+///
+/// bincode is not self-describing, so the schema version must be bumped whenever the settings type changes.
+#[cfg(feature = "bincode")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode {
+    /// Schema version stored in the file header. Files with a different version are rejected.
+    pub schema: u32,
+}
+
+#[cfg(feature = "bincode")]
+impl Format for Bincode {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        let mut out = header::write(header::BINCODE, self.schema);
+        bincode::serialize_into(&mut out, value)?;
+        Ok(out)
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        use bincode::Options;
+
+        let mut payload = header::strip(bytes, header::BINCODE, self.schema)?;
+        let options = bincode::DefaultOptions::new().with_fixint_encoding();
+        // Trailing bytes are only rejected by `Options::deserialize`, so they are checked by hand.
+        let value = serde_path_to_error::deserialize(&mut bincode::Deserializer::with_reader(
+            &mut payload,
+            options,
+        ))
+        .map_err(|e| DecodeError::from_path_error(e, |_| None))?;
+        if !payload.is_empty() {
+            return Err(DecodeError::new(format!(
+                "{} unexpected bytes after the settings",
+                payload.len()
+            )));
+        }
+        Ok(value)
+    }
+}
+
+/// Format chosen at runtime from the file extension, or from the contents for files without one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Auto {
+    #[default]
+    Toml,
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "yaml")]
+    Yaml,
+    #[cfg(feature = "ron")]
+    Ron,
+    #[cfg(feature = "json5")]
+    Json5,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "bincode")]
+    Bincode,
+}
+
+impl Auto {
+    /// Formats tried in order when sniffing contents.
+    const ALL: &'static [Self] = &[
+        Self::Toml,
+        #[cfg(feature = "json")]
+        Self::Json,
+        #[cfg(feature = "json5")]
+        Self::Json5,
+        #[cfg(feature = "ron")]
+        Self::Ron,
+        #[cfg(feature = "yaml")]
+        Self::Yaml,
+    ];
+
+    /// Format for a file extension, if it is known and enabled.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "toml" => Some(Self::Toml),
+            #[cfg(feature = "json")]
+            "json" => Some(Self::Json),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(Self::Yaml),
+            #[cfg(feature = "ron")]
+            "ron" => Some(Self::Ron),
+            #[cfg(feature = "json5")]
+            "json5" => Some(Self::Json5),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(Self::Cbor),
+            #[cfg(feature = "msgpack")]
+            "msgpack" | "mpk" => Some(Self::MessagePack),
+            #[cfg(feature = "bincode")]
+            "bincode" => Some(Self::Bincode),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of file contents from the binary header, or by finding the first text format that parses them.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        #[cfg(any(feature = "cbor", feature = "msgpack", feature = "bincode"))]
+        match header::tag(bytes) {
+            #[cfg(feature = "cbor")]
+            Some(header::CBOR) => return Some(Self::Cbor),
+            #[cfg(feature = "msgpack")]
+            Some(header::MSGPACK) => return Some(Self::MessagePack),
+            #[cfg(feature = "bincode")]
+            Some(header::BINCODE) => return Some(Self::Bincode),
+            Some(_) => return None,
+            None => {}
+        }
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.decode::<IgnoredAny>(bytes).is_ok())
+    }
+
+    /// Pick the format for `path` by its extension.
+    /// Files without an extension are sniffed if `contents` are available and treated as TOML otherwise.
+    pub fn detect(path: &Path, contents: Option<&[u8]>) -> Result<Self, Error> {
+        match path.extension() {
+            Some(ext) => ext.to_str().and_then(Self::from_extension),
+            None => Some(contents.and_then(Self::sniff).unwrap_or_default()),
+        }
+        .ok_or_else(|| Error::UnknownFormat {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Format for Auto {
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        match self {
+            Self::Toml => Toml.encode(value),
+            #[cfg(feature = "json")]
+            Self::Json => Json.encode(value),
+            #[cfg(feature = "yaml")]
+            Self::Yaml => Yaml.encode(value),
+            #[cfg(feature = "ron")]
+            Self::Ron => Ron.encode(value),
+            #[cfg(feature = "json5")]
+            Self::Json5 => Json5.encode(value),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => Cbor::default().encode(value),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => MessagePack::default().encode(value),
+            #[cfg(feature = "bincode")]
+            Self::Bincode => Bincode::default().encode(value),
+        }
+    }
+
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        match self {
+            Self::Toml => Toml.decode(bytes),
+            #[cfg(feature = "json")]
+            Self::Json => Json.decode(bytes),
+            #[cfg(feature = "yaml")]
+            Self::Yaml => Yaml.decode(bytes),
+            #[cfg(feature = "ron")]
+            Self::Ron => Ron.decode(bytes),
+            #[cfg(feature = "json5")]
+            Self::Json5 => Json5.decode(bytes),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => Cbor::default().decode(bytes),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => MessagePack::default().decode(bytes),
+            #[cfg(feature = "bincode")]
+            Self::Bincode => Bincode::default().decode(bytes),
+        }
+    }
+
+    fn encode_update<T>(&self, value: &T, previous: &[u8]) -> Result<Vec<u8>, EncodeError>
+    where
+        T: Serialize,
+    {
+        match self {
+            Self::Toml => Toml.encode_update(value, previous),
+            #[allow(unreachable_patterns)]
+            _ => self.encode(value),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Layered configuration merged from several files.
+
+use {
+    crate::{read, Auto, EnvOverrides, Error, Format, Settings},
+    serde::{de::DeserializeOwned, Serialize},
+    std::path::{Path, PathBuf},
+    toml::{value::Table, Value},
+};
+
+/// Configuration merged with the settings file but not written back to it.
+#[derive(Clone, Debug)]
+pub(crate) struct Layers {
+    /// Merged layers beneath the file, such as defaults and system-wide settings.
+    pub below: Value,
+    /// Contents of the file as last read or written.
+    pub file: Value,
+    /// Merged overrides on top of the file.
+    pub above: Value,
+    /// Whether the file holds complete settings rather than only what differs from the other layers.
+    /// Such files are read and written as the settings type, since not every format can hold a bare TOML value.
+    pub complete: bool,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self {
+            below: Value::Table(Table::new()),
+            file: Value::Table(Table::new()),
+            above: Value::Table(Table::new()),
+            complete: false,
+        }
+    }
+}
+
+impl Layers {
+    /// All layers merged in priority order.
+    pub fn merged(&self) -> Value {
+        let mut merged = self.below.clone();
+        merge(&mut merged, self.file.clone());
+        merge(&mut merged, self.above.clone());
+        merged
+    }
+
+    /// Reduce a serialized settings value to what should be written to the file.
+    /// Values inherited unchanged from other layers are left out.
+    pub fn strip(&self, value: Value) -> Value {
+        strip(
+            value,
+            Some(&self.below),
+            Some(&self.file),
+            Some(&self.above),
+        )
+        .unwrap_or_else(|| Value::Table(Table::new()))
+    }
+}
+
+/// Deep-merge `top` into `base`. Tables are merged key by key, any other value in `top` replaces the one in `base`.
+pub(crate) fn merge(base: &mut Value, top: Value) {
+    match (base, top) {
+        (Value::Table(base), Value::Table(top)) => {
+            for (key, value) in top {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, top) => *base = top,
+    }
+}
+
+/// Find the value at a dotted key path.
+pub(crate) fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(value, |value, part| value.as_table()?.get(part))
+}
+
+fn get<'a>(value: Option<&'a Value>, key: &str) -> Option<&'a Value> {
+    value.and_then(Value::as_table).and_then(|t| t.get(key))
+}
+
+fn strip(
+    value: Value,
+    below: Option<&Value>,
+    file: Option<&Value>,
+    above: Option<&Value>,
+) -> Option<Value> {
+    match value {
+        Value::Table(table) => {
+            let mut out = Table::new();
+            for (key, value) in table {
+                if let Some(value) =
+                    strip(value, get(below, &key), get(file, &key), get(above, &key))
+                {
+                    out.insert(key, value);
+                }
+            }
+            if out.is_empty() && !file.is_some_and(Value::is_table) {
+                None
+            } else {
+                Some(Value::Table(out))
+            }
+        }
+        value => {
+            if above == Some(&value) {
+                file.cloned()
+            } else if file.is_none() && below == Some(&value) {
+                None
+            } else {
+                Some(value)
+            }
+        }
+    }
+}
+
+/// Read a layer file into a TOML value, picking its format by extension. Missing files are skipped.
+pub(crate) fn read_layer(path: &Path) -> Result<Option<Value>, Error> {
+    read(path)?
+        .map(|bytes| {
+            Auto::detect(path, Some(&bytes))?
+                .decode(&bytes)
+                .map_err(|e| Error::deserialize(path, e))
+        })
+        .transpose()
+}
+
+/// Builder for settings merged from several files with compiled-in defaults underneath.
+///
+/// Layers are merged in priority order: defaults, layers added with [`LayeredSettings::layer_below`],
+/// the writable layer and layers added with [`LayeredSettings::layer_above`].
+/// Tables are merged key by key. Mutations are written only to the writable layer,
+/// which receives just the values that differ from what the other layers provide.
+pub struct LayeredSettings<T> {
+    writable: PathBuf,
+    defaults: Option<T>,
+    below: Vec<PathBuf>,
+    above: Vec<PathBuf>,
+    env: Option<EnvOverrides>,
+}
+
+impl<T> LayeredSettings<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Start building settings that are saved to `writable`, normally the user's configuration file.
+    pub fn new(writable: impl AsRef<Path>) -> Self {
+        Self {
+            writable: writable.as_ref().to_path_buf(),
+            defaults: None,
+            below: Vec::new(),
+            above: Vec::new(),
+            env: None,
+        }
+    }
+
+    /// Use `defaults` as the lowest layer.
+    pub fn defaults(mut self, defaults: T) -> Self {
+        self.defaults = Some(defaults);
+        self
+    }
+
+    /// Add a read-only layer beneath the writable one, such as system-wide configuration.
+    /// Each call adds a layer with higher priority than the previous one.
+    pub fn layer_below(mut self, path: impl AsRef<Path>) -> Self {
+        self.below.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add a read-only layer on top of the writable one, such as project configuration.
+    /// Each call adds a layer with higher priority than the previous one.
+    pub fn layer_above(mut self, path: impl AsRef<Path>) -> Self {
+        self.above.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Apply overrides from environment variables on top of all layers.
+    pub fn env(mut self, env: EnvOverrides) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Read and merge all layers. Missing files are skipped.
+    pub fn build(self) -> Result<Settings<T>, Error> {
+        let format = Auto::detect(&self.writable, None)?;
+        let mut layers = Layers::default();
+        if let Some(defaults) = &self.defaults {
+            layers.below = Value::try_from(defaults).map_err(|e| Error::Serialize(e.into()))?;
+        }
+        for path in &self.below {
+            if let Some(value) = read_layer(path)? {
+                merge(&mut layers.below, value);
+            }
+        }
+        let bytes = read(&self.writable)?;
+        if let Some(bytes) = &bytes {
+            layers.file = Auto::detect(&self.writable, Some(bytes))?
+                .decode(bytes)
+                .map_err(|e| Error::deserialize(&self.writable, e))?;
+        }
+        for path in &self.above {
+            if let Some(value) = read_layer(path)? {
+                merge(&mut layers.above, value);
+            }
+        }
+        let mut settings = Settings::from_layers(self.writable, format, layers, bytes.as_deref())?;
+        if let Some(env) = &self.env {
+            settings.apply_env(env)?;
+        }
+        Ok(settings)
+    }
+}
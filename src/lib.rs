@@ -2,122 +2,1206 @@
 //! This crate provides a very simple disk-based configuration storage.
 //! It supports both saving new configuration and loading a new one.
 //! Rust's type system ensures that all edits to the existing configuration are automatically saved on disk.
+//!
+//! Settings are stored as TOML by default. JSON, YAML, RON and JSON5 are available behind the `json`, `yaml`, `ron` and `json5` features.
+//! Machine-owned state can use the binary CBOR, MessagePack and bincode formats behind the `cbor`, `msgpack` and `bincode` features.
+//! [`Settings::new`] and [`Settings::load`] pick the format from the file extension.
+//! [`LayeredSettings`] merges defaults and several configuration files, saving changes to a single writable layer.
+//! [`EnvOverrides`] applies environment variables on top of loaded settings without writing them back.
+//! [`Settings::apply_overrides`] does the same for `key.path=value` assignments given on the command line.
+//! [`Settings::load_migrated`] upgrades documents written with older schema versions through a chain of [`Migrations`].
+//! With the `preserve` feature, [`Settings::set_preserve_formatting`] keeps comments and layout of hand-edited TOML files when saving.
+//! [`Settings::reload_if_changed`] picks up edits made by other programs, and a [`Watcher`] signals when they happen,
+//! using file notifications with the `watch` feature and polling otherwise.
+//! [`SharedSettings::reload_on_change`] swaps in edited contents in the background, and saves fail instead of overwriting them.
+//! [`SharedSettings`] shares settings between threads and writes the file outside of its lock.
+//! With the `async` feature, [`AsyncSettings`] loads and saves on tokio's blocking pool instead of the executor thread.
+//! [`Settings::set_backups`] keeps rotating copies of previous versions of the file that can be restored later.
+//! [`Settings::load_or_recover`] replaces a corrupted file with the newest valid backup or with defaults.
+//! [`Settings::set_validator`] and the [`Validate`] trait reject invalid settings on reload and before every save.
+//! With the `derive` feature, `#[derive(Validate)]` generates validation from attributes such as `#[setting(range = 1..=65535)]`.
+//! [`Settings::subscribe`] notifies other components of committed changes and reloads, optionally only for a single field.
+//! [`Settings::set_lock_mode`] enables advisory locks that keep concurrent processes from losing each other's updates.
+
+mod app;
+#[cfg(feature = "async")]
+mod async_settings;
+mod backup;
+mod error;
+pub mod format;
+mod layer;
+mod lock;
+mod migrate;
+mod overrides;
+#[cfg(feature = "preserve")]
+mod preserve;
+mod shared;
+mod subscribe;
+mod validate;
+mod watch;
+
+#[cfg(feature = "async")]
+pub use async_settings::{AsyncSettings, AsyncSettingsGuard};
+#[cfg(feature = "bincode")]
+pub use format::Bincode;
+#[cfg(feature = "cbor")]
+pub use format::Cbor;
+#[cfg(feature = "json")]
+pub use format::Json;
+#[cfg(feature = "json5")]
+pub use format::Json5;
+#[cfg(feature = "msgpack")]
+pub use format::MessagePack;
+#[cfg(feature = "ron")]
+pub use format::Ron;
+#[cfg(feature = "yaml")]
+pub use format::Yaml;
+#[cfg(feature = "derive")]
+pub use simple_settings_derive::Validate;
+pub use {
+    app::{AppDirs, AppSettings},
+    backup::{Backups, Recovery},
+    error::Error,
+    format::{Auto, Format, Toml},
+    layer::LayeredSettings,
+    lock::LockMode,
+    migrate::Migrations,
+    overrides::EnvOverrides,
+    shared::{SharedSettings, SharedSettingsGuard},
+    subscribe::Subscription,
+    toml,
+    validate::{FieldError, Validate, ValidationErrors},
+    watch::{Reloader, Watcher},
+};
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "derive")]
+    pub use regex::Regex;
+}
 
 use {
-    serde::{Deserialize, Serialize},
+    format::DecodeError,
+    layer::Layers,
+    lock::FileLock,
+    serde::{de::DeserializeOwned, Serialize},
     std::{
-        fs::{File, OpenOptions},
+        collections::hash_map::DefaultHasher,
+        ffi::OsString,
+        fs::{self, File},
+        hash::Hasher,
         io::{self, prelude::*},
+        mem,
         ops::{Deref, DerefMut},
-        path::Path,
+        path::{Path, PathBuf},
+        process,
+        sync::atomic::{AtomicU64, Ordering},
+        thread,
     },
+    subscribe::Subscribers,
+    watch::FileStamp,
 };
 
-/// A very simple TOML-based settings storage.
-pub struct Settings<T> {
-    file: std::fs::File,
+/// A very simple settings storage. The format is picked from the file extension by default.
+pub struct Settings<T, F = Auto> {
+    path: PathBuf,
     data: T,
+    format: F,
+    layers: Option<Box<Layers>>,
+    preserve_formatting: bool,
+    error_hook: Option<Box<ErrorHook>>,
+    /// Metadata of the file as last read or written, used to detect external changes.
+    stamp: Option<FileStamp>,
+    /// Digest of the file contents as last read or written, telling external changes from mere metadata updates.
+    contents: Option<u64>,
+    lock_mode: LockMode,
+    /// Exclusive lock held by a guard for the whole read-modify-write cycle.
+    held_lock: Option<FileLock>,
+    /// Digest of the file contents last read or written, used to skip saving unchanged data.
+    saved: Option<u64>,
+    backups: Option<Backups>,
+    validator: Option<Box<Validator<T>>>,
+    /// Set when data left half-modified by a panic could not be restored. Saving is refused while set.
+    poisoned: bool,
+    subscribers: Subscribers<T>,
+}
+
+/// Whether settings were read from an existing file or freshly created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Loaded,
+    Created,
 }
 
+/// Check run on settings before they are accepted or saved.
+pub type Validator<T> = dyn Fn(&T) -> Result<(), ValidationErrors> + Send + Sync;
+
+/// Callback receiving errors that cannot be returned to the caller, such as failed saves on guard destruction.
+pub type ErrorHook = dyn Fn(&Error) + Send + Sync;
+
 /// Guard for read access.
 pub struct SettingsGuard<'a, T> {
-    data: &'a T,
+    data: Borrow<'a, T>,
+}
+
+enum Borrow<'a, T> {
+    Ref(&'a T),
+    /// Data behind a lock held for the lifetime of the guard.
+    Locked(Box<dyn Deref<Target = T> + 'a>),
 }
 
 impl<'a, T> Deref for SettingsGuard<'a, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        &self.data
+        match &self.data {
+            Borrow::Ref(data) => data,
+            Borrow::Locked(data) => data,
+        }
     }
 }
 
-/// Guard for mutable access. Persists to disk upon destruction.
-pub struct MutableSettingsGuard<'a, T>
+/// Guard for mutable access. Persists to disk upon destruction if the data changed.
+///
+/// Use [`MutableSettingsGuard::commit`] to save explicitly and handle failures, or [`MutableSettingsGuard::rollback`]
+/// to discard the changes. Errors from the implicit save on destruction are reported to the hook set with [`Settings::set_error_hook`].
+/// Saving fails with [`Error::Conflict`] if another program changed the file since it was read.
+pub struct MutableSettingsGuard<'a, T, F = Auto>
 where
-    T: Serialize,
+    T: Serialize + DeserializeOwned,
+    F: Format,
 {
-    data: &'a mut T,
-    file: &'a mut std::fs::File,
+    settings: &'a mut Settings<T, F>,
+    committed: bool,
+    /// Copy of the data taken by [`Settings::transaction`] or for subscribers, restored on rollback.
+    /// Without it the file is read again instead.
+    snapshot: Option<T>,
 }
 
-impl<'a, T> Deref for MutableSettingsGuard<'a, T>
+impl<'a, T, F> MutableSettingsGuard<'a, T, F>
 where
-    T: Serialize,
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    /// Save the data to disk, consuming the guard.
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.committed = true;
+        self.save()
+    }
+
+    /// Save the data and notify subscribers, discarding the changes if validation rejects them.
+    fn save(&mut self) -> Result<(), Error> {
+        match self.settings.save() {
+            Ok(true) => {
+                self.settings.notify(self.snapshot.as_ref());
+                Ok(())
+            }
+            Ok(false) => Ok(()),
+            Err(e) => {
+                if let Error::Validation(_) = e {
+                    let _ = self.settings.restore(self.snapshot.take());
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Discard the changes made through the guard. Nothing is saved.
+    ///
+    /// A guard from [`Settings::transaction`] restores the data it was taken with. Other guards read the file again,
+    /// which fails if it cannot be read or parsed, and also drops fields that are not saved such as `#[serde(skip)]` ones.
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.committed = true;
+        self.settings.restore(self.snapshot.take())
+    }
+}
+
+impl<'a, T, F> Deref for MutableSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
 {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        &self.data
+        &self.settings.data
     }
 }
 
-impl<'a, T> DerefMut for MutableSettingsGuard<'a, T>
+impl<'a, T, F> DerefMut for MutableSettingsGuard<'a, T, F>
 where
-    T: Serialize,
+    T: Serialize + DeserializeOwned,
+    F: Format,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.data
+        &mut self.settings.data
     }
 }
 
-impl<'a, T> Drop for MutableSettingsGuard<'a, T>
+impl<'a, T, F> Drop for MutableSettingsGuard<'a, T, F>
 where
-    T: Serialize,
+    T: Serialize + DeserializeOwned,
+    F: Format,
 {
     fn drop(&mut self) {
-        self.file.set_len(0).unwrap();
-        self.file.sync_all().unwrap();
-        self.file.seek(std::io::SeekFrom::Start(0)).unwrap();
-        self.file
-            .write_all(&toml::to_vec(&self.data).unwrap())
-            .unwrap();
-        self.file.sync_all().unwrap();
+        if !self.committed {
+            if thread::panicking() {
+                // Changes interrupted by a panic may be incomplete, so they are discarded instead of saved.
+                if self.settings.restore(self.snapshot.take()).is_err() {
+                    self.settings.poisoned = true;
+                }
+            } else if let Err(e) = self.save() {
+                if let Some(hook) = &self.settings.error_hook {
+                    hook(&e);
+                }
+            }
+        }
+        self.settings.held_lock = None;
+    }
+}
+
+/// Path of the temporary sibling file used while saving `path`, unique to each save.
+fn temp_path(path: &Path) -> PathBuf {
+    static SAVES: AtomicU64 = AtomicU64::new(0);
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(
+        ".{}.{}.tmp",
+        process::id(),
+        SAVES.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(name)
+}
+
+/// Follow symbolic links at `path` to the file they point to, which may not exist yet.
+fn resolve_links(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(dir) => dir.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::other("too many levels of symbolic links"))
+}
+
+/// Replace the contents of `path` so that it always holds either the old or the new complete document.
+///
+/// Data is written to a temporary file in the same directory, flushed to disk and renamed over the target.
+/// The file keeps its permissions, and a symbolic link is kept in place by replacing the file it points to.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let path = &resolve_links(path)?;
+    let permissions = fs::metadata(path).ok().map(|m| m.permissions());
+    let tmp = temp_path(path);
+    let res = (|| {
+        let mut file = File::create(&tmp)?;
+        // Restricted before any data is written, so that secrets are never readable by others.
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res?;
+
+    sync_dir(path)
+}
+
+/// Flush the directory entry of `path` so that the rename survives a crash.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Deserialize settings merged from layers. `path` is reported in errors.
+fn from_value<T: DeserializeOwned>(path: &Path, value: toml::Value) -> Result<T, Error> {
+    serde_path_to_error::deserialize(value)
+        .map_err(|e| Error::deserialize(path, DecodeError::from_path_error(e, |_| None)))
+}
+
+/// Hash of file contents, compared instead of keeping a copy of them.
+fn hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Fail with [`Error::Conflict`] if another program changed the file at `path` since it was last read or written,
+/// when it had `stamp` and the contents hashed as `contents`. Files that were only touched or were deleted do not conflict.
+fn check_unchanged(
+    path: &Path,
+    stamp: Option<FileStamp>,
+    contents: Option<u64>,
+) -> Result<(), Error> {
+    if FileStamp::of(path) == stamp {
+        return Ok(());
+    }
+    match read(path)? {
+        Some(bytes) if Some(hash(&bytes)) != contents => Err(Error::Conflict {
+            path: path.to_path_buf(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Read the file at `path`, returning `None` if it does not exist.
+fn read(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::io(path, e)),
+    }
+}
+
+/// File contents serialized for saving.
+struct Pending {
+    data: Vec<u8>,
+    /// What the writable layer will contain, for layered settings.
+    file: Option<toml::Value>,
+    backups: Option<Backups>,
+}
+
+impl Pending {
+    /// Hash of the contents, compared instead of keeping a copy of the last saved file.
+    fn digest(&self) -> u64 {
+        hash(&self.data)
+    }
+
+    /// Back up the current file and write the contents to `path`. Layered settings create missing parent directories.
+    fn write(&self, path: &Path) -> Result<(), Error> {
+        if self.file.is_some() {
+            create_parent(path)?;
+        }
+        if let Some(backups) = &self.backups {
+            backups.take(path)?;
+        }
+        write_atomic(path, &self.data).map_err(|e| Error::io(path, e))
+    }
+}
+
+/// Create the missing parent directories of `path`.
+fn create_parent(path: &Path) -> Result<(), Error> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+    }
+    Ok(())
+}
+
+impl<T, F> Settings<T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    /// Serialize `value` into file contents, patching the existing file if formatting is preserved.
+    fn encode<V: Serialize>(&self, value: &V) -> Result<Vec<u8>, Error> {
+        let previous = if self.preserve_formatting {
+            read(&self.path)?
+        } else {
+            None
+        };
+        match previous {
+            Some(previous) => self.format.encode_update(value, &previous),
+            None => self.format.encode(value),
+        }
+        .map_err(Error::Serialize)
+    }
+
+    /// Lock the file unless locking is disabled or a guard already holds the lock.
+    fn lock_file(&self, exclusive: bool) -> Result<Option<FileLock>, Error> {
+        if self.held_lock.is_some() {
+            return Ok(None);
+        }
+        lock::acquire(&self.path, self.lock_mode, exclusive)
+    }
+
+    /// Run the validator, if any, on `data`.
+    fn check(&self, data: &T) -> Result<(), Error> {
+        match &self.validator {
+            Some(validator) => validator(data).map_err(Error::Validation),
+            None => Ok(()),
+        }
+    }
+
+    /// Serialize current data into the file contents to be written.
+    fn prepare(&self) -> Result<Pending, Error> {
+        if self.poisoned {
+            return Err(Error::Poisoned {
+                path: self.path.clone(),
+            });
+        }
+        self.check(&self.data)?;
+        match &self.layers {
+            None => Ok(Pending {
+                data: self.encode(&self.data)?,
+                file: None,
+                backups: self.backups.clone(),
+            }),
+            Some(layers) => {
+                let value =
+                    toml::Value::try_from(&self.data).map_err(|e| Error::Serialize(e.into()))?;
+                let file = layers.strip(value);
+                let data = if layers.complete {
+                    self.encode(&from_value::<T>(&self.path, file.clone())?)?
+                } else {
+                    self.encode(&file)?
+                };
+                Ok(Pending {
+                    data,
+                    file: Some(file),
+                    backups: self.backups.clone(),
+                })
+            }
+        }
+    }
+
+    /// Record that a prepared save with contents hashed as `digest` and `file` as the writable layer has been written.
+    fn finish(&mut self, file: Option<toml::Value>, digest: u64) {
+        if let (Some(layers), Some(file)) = (&mut self.layers, file) {
+            layers.file = file;
+        }
+        self.stamp = FileStamp::of(&self.path);
+        self.contents = Some(digest);
+    }
+
+    /// Write current data to disk unless it is unchanged since it was last read or written.
+    /// Returns whether the file was written. Fails if another program changed the file in the meantime.
+    fn save(&mut self) -> Result<bool, Error> {
+        let pending = self.prepare()?;
+        let digest = pending.digest();
+        if self.saved == Some(digest) {
+            return Ok(false);
+        }
+        let _lock = self.lock_file(true)?;
+        check_unchanged(&self.path, self.stamp, self.contents)?;
+        pending.write(&self.path)?;
+        self.finish(pending.file, digest);
+        self.saved = Some(digest);
+        Ok(true)
     }
 }
 
 impl<T> Settings<T>
 where
-    T: Serialize + for<'de> Deserialize<'de>,
+    T: Serialize + DeserializeOwned,
 {
     /// Create configuration and store it to disk.
-    pub fn new(path: impl AsRef<Path>, data: T) -> io::Result<Self> {
-        let mut s = Self {
-            file: File::create(path)?,
-            data,
+    /// The format is chosen by the file extension, defaulting to TOML for files without one.
+    pub fn new(path: impl AsRef<Path>, data: T) -> Result<Self, Error> {
+        let format = Auto::detect(path.as_ref(), None)?;
+        Self::new_with_format(path, data, format)
+    }
+
+    /// Load configuration from disk. Returns `None` if the file does not exist.
+    /// The format is chosen by the file extension, or guessed from the contents for files without one.
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = path.as_ref();
+        match read(path)? {
+            Some(bytes) => {
+                let format = Auto::detect(path, Some(&bytes))?;
+                Self::from_bytes(path, &bytes, format).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Load configuration from disk and validate it, enabling validation before every save.
+    /// Returns `None` if the file does not exist. See [`Settings::enable_validation`].
+    pub fn load_validated(path: impl AsRef<Path>) -> Result<Option<Self>, Error>
+    where
+        T: Validate + 'static,
+    {
+        let mut settings = Self::load(path)?;
+        if let Some(settings) = &mut settings {
+            settings.enable_validation()?;
+        }
+        Ok(settings)
+    }
+
+    /// Load configuration from disk, or create it from `T::default()` if the file does not exist.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<(Self, Origin), Error>
+    where
+        T: Default,
+    {
+        Self::load_or_else(path, T::default)
+    }
+
+    /// Load configuration from disk, or create it from `init` if the file does not exist.
+    /// Missing parent directories are created.
+    pub fn load_or_else(
+        path: impl AsRef<Path>,
+        init: impl FnOnce() -> T,
+    ) -> Result<(Self, Origin), Error> {
+        let path = path.as_ref();
+        match read(path)? {
+            Some(bytes) => {
+                let format = Auto::detect(path, Some(&bytes))?;
+                Ok((Self::from_bytes(path, &bytes, format)?, Origin::Loaded))
+            }
+            None => {
+                let format = Auto::detect(path, None)?;
+                Ok((Self::create(path, init(), format)?, Origin::Created))
+            }
+        }
+    }
+
+    /// Load configuration from disk, upgrading it to the current schema version with `migrations` first.
+    /// Returns `None` if the file does not exist.
+    ///
+    /// A migrated document is written back and the original file is kept next to it as `<file>.v<version>.bak`.
+    pub fn load_migrated(
+        path: impl AsRef<Path>,
+        migrations: &Migrations,
+    ) -> Result<Option<Self>, Error> {
+        let path = path.as_ref();
+        match read(path)? {
+            Some(bytes) => {
+                let format = Auto::detect(path, Some(&bytes))?;
+                Self::from_bytes_migrated(path, &bytes, format, migrations).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Load configuration from disk, recovering if the file cannot be parsed.
+    /// Creates the file from `T::default()` if it does not exist.
+    ///
+    /// A file that fails to parse is moved aside as `<file>.corrupt-<timestamp>` and replaced with the newest of `backups`
+    /// that parses, or with `T::default()` if there is none. The returned [`Recovery`] describes what happened,
+    /// so that the application can warn the user. `backups` are enabled on the returned settings.
+    pub fn load_or_recover(
+        path: impl AsRef<Path>,
+        backups: Backups,
+    ) -> Result<(Self, Option<Recovery>), Error>
+    where
+        T: Default,
+    {
+        let path = path.as_ref();
+        let (mut settings, recovery) = match read(path)? {
+            Some(bytes) => {
+                match Self::from_bytes(path, &bytes, Auto::detect(path, Some(&bytes))?) {
+                    Ok(settings) => (settings, None),
+                    Err(error @ Error::Deserialize { .. }) => {
+                        let (settings, recovery) =
+                            Self::recover(path, error, &backups, Auto::detect, T::default)?;
+                        (settings, Some(recovery))
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            None => (
+                Self::create(path, T::default(), Auto::detect(path, None)?)?,
+                None,
+            ),
         };
-        let _ = s.guard_mut();
+        settings.set_backups(backups);
+        Ok((settings, recovery))
+    }
+}
+
+impl<T, F> Settings<T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    /// Create configuration in the given format and store it to disk.
+    pub fn new_with_format(path: impl AsRef<Path>, data: T, format: F) -> Result<Self, Error> {
+        let mut s = Self::from_parts(path.as_ref().to_path_buf(), data, format, None);
+        s.save()?;
         Ok(s)
     }
 
-    /// Load configuration from disk.
-    pub fn load(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
-        let path = path.as_ref().to_path_buf();
-        OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(&path)
-            .map(Some)
-            .unwrap_or_else(|_| None)
-            .map(|mut file| {
-                let mut s = String::new();
-                file.read_to_string(&mut s)?;
-                Ok(Self {
-                    file,
-                    data: toml::from_str(&s)?,
-                })
-            })
+    /// Load configuration in the given format from disk. Returns `None` if the file does not exist.
+    pub fn load_with_format(path: impl AsRef<Path>, format: F) -> Result<Option<Self>, Error> {
+        let path = path.as_ref();
+        read(path)?
+            .map(|bytes| Self::from_bytes(path, &bytes, format))
+            .transpose()
+    }
+
+    /// Load configuration in the given format from disk, or create it from `init` if the file does not exist.
+    /// Missing parent directories are created.
+    pub fn load_or_else_with_format(
+        path: impl AsRef<Path>,
+        format: F,
+        init: impl FnOnce() -> T,
+    ) -> Result<(Self, Origin), Error> {
+        let path = path.as_ref();
+        match read(path)? {
+            Some(bytes) => Ok((Self::from_bytes(path, &bytes, format)?, Origin::Loaded)),
+            None => Ok((Self::create(path, init(), format)?, Origin::Created)),
+        }
+    }
+
+    fn create(path: &Path, data: T, format: F) -> Result<Self, Error> {
+        create_parent(path)?;
+        Self::new_with_format(path, data, format)
+    }
+
+    /// Load configuration in the given format from disk, upgrading it to the current schema version with `migrations` first.
+    /// Returns `None` if the file does not exist.
+    pub fn load_migrated_with_format(
+        path: impl AsRef<Path>,
+        format: F,
+        migrations: &Migrations,
+    ) -> Result<Option<Self>, Error> {
+        let path = path.as_ref();
+        read(path)?
+            .map(|bytes| Self::from_bytes_migrated(path, &bytes, format, migrations))
             .transpose()
     }
 
+    /// Replace the file at `path`, rejected because of `error`, with the newest backup that parses or with `init()`.
+    /// The rejected file is quarantined. `format` picks the format for the given contents.
+    fn recover(
+        path: &Path,
+        error: Error,
+        backups: &Backups,
+        format: impl Fn(&Path, Option<&[u8]>) -> Result<F, Error>,
+        init: impl FnOnce() -> T,
+    ) -> Result<(Self, Recovery), Error> {
+        let quarantined = backup::quarantine(path)?;
+        for backup in backups.list(path)? {
+            let bytes = match fs::read(&backup) {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+            let mut settings = match Self::from_bytes(path, &bytes, format(path, Some(&bytes))?) {
+                Ok(settings) => settings,
+                Err(_) => continue,
+            };
+            write_atomic(path, &bytes).map_err(|e| Error::io(path, e))?;
+            settings.stamp = FileStamp::of(path);
+            let recovery = Recovery {
+                error,
+                quarantined,
+                restored_from: Some(backup),
+            };
+            return Ok((settings, recovery));
+        }
+        let settings = Self::new_with_format(path, init(), format(path, None)?)?;
+        let recovery = Recovery {
+            error,
+            quarantined,
+            restored_from: None,
+        };
+        Ok((settings, recovery))
+    }
+
+    fn from_bytes_migrated(
+        path: &Path,
+        bytes: &[u8],
+        format: F,
+        migrations: &Migrations,
+    ) -> Result<Self, Error> {
+        let mut doc: toml::Value = format
+            .decode(bytes)
+            .map_err(|e| Error::deserialize(path, e))?;
+        let migrated =
+            migrations
+                .migrate(&mut doc)
+                .map_err(|(version, message)| Error::Migration {
+                    path: path.to_path_buf(),
+                    version,
+                    message,
+                })?;
+        let data = from_value(path, doc.clone())?;
+        migrations
+            .check(&data)
+            .map_err(|message| Error::Migration {
+                path: path.to_path_buf(),
+                version: None,
+                message,
+            })?;
+        let mut contents = hash(bytes);
+        if let Some(version) = migrated {
+            let backup = migrate::backup_path(path, version);
+            write_atomic(&backup, bytes).map_err(|e| Error::io(&backup, e))?;
+            let migrated = format.encode(&doc).map_err(Error::Serialize)?;
+            write_atomic(path, &migrated).map_err(|e| Error::io(path, e))?;
+            contents = hash(&migrated);
+        }
+        let mut s = Self::from_parts(path.to_path_buf(), data, format, None);
+        s.contents = Some(contents);
+        s.mark_saved();
+        Ok(s)
+    }
+
+    fn from_bytes(path: &Path, bytes: &[u8], format: F) -> Result<Self, Error> {
+        let data = format
+            .decode(bytes)
+            .map_err(|e| Error::deserialize(path, e))?;
+        let mut s = Self::from_parts(path.to_path_buf(), data, format, None);
+        s.contents = Some(hash(bytes));
+        s.mark_saved();
+        Ok(s)
+    }
+
+    /// Create settings from `layers`, where `bytes` are the contents of the writable layer if it exists.
+    fn from_layers(
+        path: PathBuf,
+        format: F,
+        layers: Layers,
+        bytes: Option<&[u8]>,
+    ) -> Result<Self, Error> {
+        let data = from_value(&path, layers.merged())?;
+        let mut s = Self::from_parts(path, data, format, Some(Box::new(layers)));
+        s.contents = bytes.map(hash);
+        s.mark_saved();
+        Ok(s)
+    }
+
+    fn from_parts(path: PathBuf, data: T, format: F, layers: Option<Box<Layers>>) -> Self {
+        let stamp = FileStamp::of(&path);
+        Self {
+            path,
+            data,
+            format,
+            layers,
+            preserve_formatting: false,
+            error_hook: None,
+            stamp,
+            contents: None,
+            lock_mode: LockMode::Disabled,
+            held_lock: None,
+            saved: None,
+            backups: None,
+            validator: None,
+            poisoned: false,
+            subscribers: Subscribers::default(),
+        }
+    }
+
+    /// Remember the current data as matching the file, so that saving it again is skipped.
+    fn mark_saved(&mut self) {
+        self.saved = self.digest();
+    }
+
+    /// Digest of the file contents the current data would be saved as.
+    fn digest(&self) -> Option<u64> {
+        self.prepare().ok().map(|pending| pending.digest())
+    }
+
+    /// Pass the change from `old`, the data before a saved change, to subscribers.
+    fn notify(&self, old: Option<&T>) {
+        if let Some(old) = old {
+            self.subscribers.notify(old, &self.data);
+        }
+    }
+
     /// Lock configuration for read access.
-    pub fn guard(&self) -> SettingsGuard<T> {
-        SettingsGuard { data: &self.data }
+    pub fn guard(&self) -> SettingsGuard<'_, T> {
+        SettingsGuard {
+            data: Borrow::Ref(&self.data),
+        }
     }
 
     /// Lock configuration for mutable access. The created guard can be used for mutable access. Data will be saved on disk upon guard's destruction.
-    pub fn guard_mut(&mut self) -> MutableSettingsGuard<T> {
+    pub fn guard_mut(&mut self) -> MutableSettingsGuard<'_, T, F> {
         MutableSettingsGuard {
-            data: &mut self.data,
-            file: &mut self.file,
+            snapshot: self.subscribers.snapshot(&self.data),
+            settings: self,
+            committed: false,
         }
     }
+
+    /// Like [`Settings::guard_mut`], but the data is cloned first so that [`MutableSettingsGuard::rollback`]
+    /// restores it exactly instead of reading the file again.
+    pub fn transaction(&mut self) -> MutableSettingsGuard<'_, T, F>
+    where
+        T: Clone,
+    {
+        MutableSettingsGuard {
+            snapshot: Some(self.data.clone()),
+            settings: self,
+            committed: false,
+        }
+    }
+
+    /// Replace the data with `snapshot` to discard changes made through a guard, or read the file again without one.
+    fn restore(&mut self, snapshot: Option<T>) -> Result<(), Error> {
+        match snapshot {
+            Some(snapshot) => {
+                self.data = snapshot;
+                Ok(())
+            }
+            None => self.reload(),
+        }
+    }
+
+    /// Lock configuration for mutable access, holding the inter-process lock until the guard is dropped.
+    ///
+    /// With locking enabled through [`Settings::set_lock_mode`], the file is locked exclusively
+    /// and reloaded if another process changed it, so that no concurrent update is lost.
+    /// Its contents are compared rather than its metadata, which can look unchanged after a quick edit.
+    /// Without locking this is the same as [`Settings::guard_mut`].
+    pub fn lock(&mut self) -> Result<MutableSettingsGuard<'_, T, F>, Error> {
+        self.lock_for_update()?;
+        Ok(self.guard_mut())
+    }
+
+    /// Take the inter-process lock held by a guard, reloading the file if another process changed it.
+    fn lock_for_update(&mut self) -> Result<(), Error> {
+        self.held_lock = lock::acquire(&self.path, self.lock_mode, true)?;
+        if self.held_lock.is_some() {
+            let stamp = FileStamp::of(&self.path);
+            let res = read(&self.path).and_then(|bytes| {
+                if bytes.as_deref().map(hash) == self.contents {
+                    self.stamp = stamp;
+                    Ok(())
+                } else {
+                    self.reread(stamp, bytes)
+                }
+            });
+            if let Err(e) = res {
+                self.held_lock = None;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Modify configuration with `f` and save it to disk, returning the closure's result.
+    /// The file stays locked for the whole update if locking is enabled.
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> Result<R, Error> {
+        let mut guard = self.lock()?;
+        let r = f(&mut guard);
+        guard.commit()?;
+        Ok(r)
+    }
+
+    /// Modify configuration with `f` and save it to disk if it succeeds.
+    /// If `f` returns an error, its changes are rolled back, nothing is saved and the error is returned.
+    /// The data is cloned beforehand as with [`Settings::transaction`].
+    pub fn try_update<R, E>(&mut self, f: impl FnOnce(&mut T) -> Result<R, E>) -> Result<R, E>
+    where
+        T: Clone,
+        E: From<Error>,
+    {
+        self.lock_for_update()?;
+        let mut guard = self.transaction();
+        match f(&mut guard) {
+            Ok(r) => {
+                guard.commit()?;
+                Ok(r)
+            }
+            Err(e) => {
+                guard.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Apply overrides from environment variables on top of the current data.
+    /// Overridden values are not written back to the file unless they are changed through a guard.
+    pub fn apply_env(&mut self, env: &EnvOverrides) -> Result<(), Error> {
+        let current = toml::Value::try_from(&self.data).map_err(|e| Error::Serialize(e.into()))?;
+        let overrides = env.collect(&current)?;
+        let (data, layers) = self.push_overrides(current, overrides)?;
+        self.data = data;
+        self.layers = Some(layers);
+        Ok(())
+    }
+
+    /// Apply `key.path=value` overrides, such as those given with a `--set` command line option, on top of the current data.
+    /// Values are parsed as TOML literals. Keys must exist in the settings type and values must match their types.
+    /// Overridden values are not written back to the file unless they are changed through a guard.
+    pub fn apply_overrides<I>(&mut self, overrides: I) -> Result<(), Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let assignments = overrides
+            .into_iter()
+            .map(|assignment| assignment.as_ref().to_string())
+            .collect::<Vec<_>>();
+        let current = toml::Value::try_from(&self.data).map_err(|e| Error::Serialize(e.into()))?;
+        let overrides = overrides::parse_assignments(&assignments, &current)?;
+        let (data, layers) = self.push_overrides(current, overrides)?;
+        let value = toml::Value::try_from(&data).map_err(|e| Error::Serialize(e.into()))?;
+        overrides::check_keys(&assignments, &value)?;
+        self.data = data;
+        self.layers = Some(layers);
+        Ok(())
+    }
+
+    /// Merge `overrides` into the ephemeral top layer, returning the resulting data and layers.
+    /// `current` is the serialized current data.
+    fn push_overrides(
+        &self,
+        current: toml::Value,
+        overrides: toml::Value,
+    ) -> Result<(T, Box<Layers>), Error> {
+        let mut layers = match &self.layers {
+            Some(layers) => layers.clone(),
+            None => Box::new(Layers {
+                file: current,
+                complete: true,
+                ..Layers::default()
+            }),
+        };
+        layer::merge(&mut layers.above, overrides.clone());
+        let data = from_value(&self.path, layers.merged()).map_err(|e| match e {
+            Error::Deserialize {
+                key: Some(key),
+                message,
+                ..
+            } if layer::lookup(&overrides, &key).is_some() => Error::Override { key, message },
+            e => e,
+        })?;
+        Ok((data, layers))
+    }
+
+    /// Read the file again and replace the current data with its contents.
+    /// If the file cannot be read or parsed, the error is returned and the current data is kept.
+    /// Layers and overrides stay in place on top of the new contents.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        let _lock = self.lock_file(false)?;
+        let stamp = FileStamp::of(&self.path);
+        let bytes = read(&self.path)?;
+        self.reread(stamp, bytes)
+    }
+
+    /// Replace the current data with `bytes`, the contents of the file read when it had `stamp`.
+    fn reread(&mut self, stamp: Option<FileStamp>, bytes: Option<Vec<u8>>) -> Result<(), Error> {
+        let (data, file) = self.parse(bytes.as_deref())?;
+        self.replace(data, file);
+        self.stamp = stamp;
+        self.contents = bytes.as_deref().map(hash);
+        Ok(())
+    }
+
+    /// Parse file contents into data, merged with the other layers if there are any.
+    /// Returns the data and the new contents of the writable layer. `None` stands for a missing file.
+    fn parse(&self, bytes: Option<&[u8]>) -> Result<(T, Option<toml::Value>), Error> {
+        let path = &self.path;
+        match &self.layers {
+            None => {
+                let bytes = bytes
+                    .ok_or_else(|| Error::io(path, io::Error::from(io::ErrorKind::NotFound)))?;
+                let data = self
+                    .format
+                    .decode(bytes)
+                    .map_err(|e| Error::deserialize(path, e))?;
+                self.check(&data)?;
+                Ok((data, None))
+            }
+            Some(layers) => {
+                let file = match bytes {
+                    Some(bytes) if layers.complete => {
+                        let data: T = self
+                            .format
+                            .decode(bytes)
+                            .map_err(|e| Error::deserialize(path, e))?;
+                        toml::Value::try_from(&data).map_err(|e| Error::Serialize(e.into()))?
+                    }
+                    Some(bytes) => self
+                        .format
+                        .decode(bytes)
+                        .map_err(|e| Error::deserialize(path, e))?,
+                    None => toml::Value::Table(Default::default()),
+                };
+                let mut merged = layers.below.clone();
+                layer::merge(&mut merged, file.clone());
+                layer::merge(&mut merged, layers.above.clone());
+                let data = from_value(path, merged)?;
+                self.check(&data)?;
+                Ok((data, Some(file)))
+            }
+        }
+    }
+
+    /// Replace data with contents parsed from the file, notifying subscribers if it changed.
+    fn replace(&mut self, data: T, file: Option<toml::Value>) {
+        let old = mem::replace(&mut self.data, data);
+        if let (Some(layers), Some(file)) = (&mut self.layers, file) {
+            layers.file = file;
+        }
+        self.poisoned = false;
+        let saved = self.saved;
+        self.mark_saved();
+        if saved.is_none() || self.saved != saved {
+            self.subscribers.notify(&old, &self.data);
+        }
+    }
+
+    /// Reload the file if it was modified since it was last read or written.
+    /// Returns whether the data was reloaded. On error the current data is kept.
+    pub fn reload_if_changed(&mut self) -> Result<bool, Error> {
+        if FileStamp::of(&self.path) == self.stamp {
+            return Ok(false);
+        }
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// Start watching the file for changes made by other programs.
+    pub fn watch(&self) -> Watcher {
+        Watcher::new(&self.path)
+    }
+
+    /// Keep comments, key order and blank lines of the existing file when saving by patching only changed keys.
+    /// Only TOML files support this, and only with the `preserve` feature; other files are rewritten from scratch.
+    pub fn set_preserve_formatting(&mut self, preserve: bool) {
+        // Unchanged data is recognized by its encoding, which depends on the mode.
+        let unchanged = self.saved.is_some() && self.saved == self.digest();
+        self.preserve_formatting = preserve;
+        if unchanged {
+            self.mark_saved();
+        }
+    }
+
+    /// Guard the file with advisory locks: shared while it is reloaded, exclusive while it is saved.
+    /// Use [`Settings::lock`] or [`Settings::update`] to hold the lock across a whole read-modify-write cycle.
+    pub fn set_lock_mode(&mut self, mode: LockMode) {
+        self.lock_mode = mode;
+    }
+
+    /// Check data with `validator` before every save and reload, rejecting invalid data with [`Error::Validation`].
+    /// Rejected saves leave the file untouched, and changes rejected at the end of a guard are discarded.
+    /// The current data is checked right away; the validator stays in place even if it fails.
+    pub fn set_validator(
+        &mut self,
+        validator: impl Fn(&T) -> Result<(), ValidationErrors> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        self.validator = Some(Box::new(validator));
+        self.check(&self.data)
+    }
+
+    /// Validate data with its [`Validate`] implementation before every save and reload. See [`Settings::set_validator`].
+    pub fn enable_validation(&mut self) -> Result<(), Error>
+    where
+        T: Validate + 'static,
+    {
+        self.set_validator(T::validate)
+    }
+
+    /// Keep copies of the file as it was before each save. See [`Backups`].
+    pub fn set_backups(&mut self, backups: Backups) {
+        self.backups = Some(backups);
+    }
+
+    /// Backups of the file, newest first. Empty if backups are not enabled.
+    pub fn list_backups(&self) -> Result<Vec<PathBuf>, Error> {
+        match &self.backups {
+            Some(backups) => backups.list(&self.path),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Replace the file and the current data with the `n`-th most recent backup, counting from 1.
+    /// The current file is backed up first, so a restore can itself be undone.
+    pub fn restore_backup(&mut self, n: usize) -> Result<(), Error> {
+        let backups = self.list_backups()?;
+        let backup = n
+            .checked_sub(1)
+            .and_then(|i| backups.get(i))
+            .ok_or_else(|| {
+                Error::io(
+                    &self.path,
+                    io::Error::new(io::ErrorKind::NotFound, format!("no backup number {}", n)),
+                )
+            })?;
+        let bytes = fs::read(backup).map_err(|e| Error::io(backup, e))?;
+        let (data, file) = self.parse(Some(&bytes))?;
+
+        let _lock = self.lock_file(true)?;
+        let pending = Pending {
+            data: bytes,
+            file,
+            backups: self.backups.clone(),
+        };
+        let digest = pending.digest();
+        pending.write(&self.path)?;
+        self.replace(data, pending.file);
+        self.stamp = FileStamp::of(&self.path);
+        self.contents = Some(digest);
+        Ok(())
+    }
+
+    /// Whether a panic left the data in a state that could not be restored.
+    /// Poisoned settings are not saved until [`Settings::reload`] succeeds or [`Settings::clear_poison`] is called.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Allow saving the current data again after it was poisoned by a panic.
+    pub fn clear_poison(&mut self) {
+        self.poisoned = false;
+    }
+
+    /// Call `callback` with the old and new data after every save that changes the file
+    /// and every reload or restore that changes the data.
+    ///
+    /// Callbacks run while the settings are borrowed or locked, so they must not access them through a shared handle.
+    /// While there are subscribers, the data is cloned whenever a guard is taken to keep the old side of the change.
+    pub fn subscribe(&mut self, callback: impl Fn(&T, &T) + Send + Sync + 'static) -> Subscription
+    where
+        T: Clone,
+    {
+        self.subscribers
+            .add(T::clone, move |change| callback(change.old, change.new))
+    }
+
+    /// Like [`Settings::subscribe`], but only for changes to the value at a dotted key path such as `log.path`.
+    pub fn subscribe_path(
+        &mut self,
+        path: impl Into<String>,
+        callback: impl Fn(&T, &T) + Send + Sync + 'static,
+    ) -> Subscription
+    where
+        T: Clone,
+    {
+        let path = path.into();
+        self.subscribers.add(T::clone, move |change| {
+            if change.path_changed(&path) {
+                callback(change.old, change.new)
+            }
+        })
+    }
+
+    /// Call `callback` with the old and new value of the field picked by `select`, such as `|s| &s.log.path`, when it changes.
+    /// See [`Settings::subscribe`].
+    pub fn subscribe_field<V>(
+        &mut self,
+        select: impl Fn(&T) -> &V + Send + Sync + 'static,
+        callback: impl Fn(&V, &V) + Send + Sync + 'static,
+    ) -> Subscription
+    where
+        T: Clone,
+        V: PartialEq + ?Sized,
+    {
+        self.subscribers.add(T::clone, move |change| {
+            let (old, new) = (select(change.old), select(change.new));
+            if old != new {
+                callback(old, new)
+            }
+        })
+    }
+
+    /// Cancel a subscription, returning whether it was active.
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        self.subscribers.remove(subscription)
+    }
+
+    /// Set the callback that receives errors from saves performed on guard destruction.
+    /// Without a hook such errors are silently ignored.
+    pub fn set_error_hook(&mut self, hook: impl Fn(&Error) + Send + Sync + 'static) {
+        self.error_hook = Some(Box::new(hook));
+    }
 }
@@ -0,0 +1,106 @@
+//! Advisory locking between processes sharing a settings file.
+
+use {
+    crate::Error,
+    std::{
+        ffi::OsString,
+        fs::{File, OpenOptions, TryLockError},
+        path::{Path, PathBuf},
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+/// How to acquire the lock guarding a settings file against concurrent access from other processes.
+///
+/// Locks are advisory: they only exclude other programs that lock the same file through this crate.
+/// They are taken on a hidden `.<file>.lock` file next to the settings file, since saves replace the settings file itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Do not lock.
+    #[default]
+    Disabled,
+    /// Wait until the lock becomes available.
+    Block,
+    /// Fail with [`Error::Lock`] if the lock is held by another process.
+    Try,
+    /// Wait at most the given time, then fail with [`Error::Lock`].
+    Timeout(Duration),
+}
+
+/// A held lock, released on drop.
+pub(crate) struct FileLock {
+    _file: File,
+}
+
+/// Interval between attempts while waiting with a timeout.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Path of the lock file guarding `path`.
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// Lock the settings file at `path`, shared for reading or exclusive for writing.
+/// Returns `None` if locking is disabled.
+pub(crate) fn acquire(
+    path: &Path,
+    mode: LockMode,
+    exclusive: bool,
+) -> Result<Option<FileLock>, Error> {
+    if mode == LockMode::Disabled {
+        return Ok(None);
+    }
+    let lock_path = lock_path(path);
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|e| Error::io(&lock_path, e))?;
+    let try_lock = || {
+        if exclusive {
+            file.try_lock()
+        } else {
+            file.try_lock_shared()
+        }
+    };
+    let contended = || Error::Lock {
+        path: path.to_path_buf(),
+    };
+
+    match mode {
+        LockMode::Disabled => unreachable!(),
+        LockMode::Block => {
+            let locked = if exclusive {
+                file.lock()
+            } else {
+                file.lock_shared()
+            };
+            locked.map_err(|e| Error::io(&lock_path, e))?;
+        }
+        LockMode::Try => match try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(contended()),
+            Err(TryLockError::Error(e)) => return Err(Error::io(&lock_path, e)),
+        },
+        LockMode::Timeout(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                match try_lock() {
+                    Ok(()) => break,
+                    Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                        thread::sleep(RETRY_INTERVAL)
+                    }
+                    Err(TryLockError::WouldBlock) => return Err(contended()),
+                    Err(TryLockError::Error(e)) => return Err(Error::io(&lock_path, e)),
+                }
+            }
+        }
+    }
+    Ok(Some(FileLock { _file: file }))
+}
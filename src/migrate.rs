@@ -0,0 +1,133 @@
+//! Schema versioning of settings documents.
+
+use {
+    serde::Serialize,
+    std::{
+        ffi::OsString,
+        path::{Path, PathBuf},
+    },
+    toml::Value,
+};
+
+type Step = dyn Fn(&mut Value) -> Result<(), String> + Send + Sync;
+
+/// Chain of migrations that upgrade raw settings documents to the current schema version before deserialization.
+///
+/// By convention the document stores its schema version as an integer under the `version` key,
+/// and the settings type includes that field with the current version as its default.
+/// Loading fails if the settings type does not store the current version, as saving would drop it.
+/// Documents without the key are treated as version 1.
+/// The first migration upgrades version 1 to 2, the second 2 to 3 and so on.
+#[derive(Default)]
+pub struct Migrations {
+    key: Option<String>,
+    steps: Vec<Box<Step>>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store the schema version under `key` instead of `version`.
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Add a migration upgrading the document from the latest version so far to the next one.
+    pub fn then(
+        mut self,
+        step: impl Fn(&mut Value) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// Schema version produced by the last migration.
+    pub fn current(&self) -> u32 {
+        self.steps.len() as u32 + 1
+    }
+
+    fn version_key(&self) -> &str {
+        self.key.as_deref().unwrap_or("version")
+    }
+
+    /// Upgrade `doc` to the current version. Returns the original version if any migration ran.
+    /// Errors carry the version being migrated from, if known.
+    pub(crate) fn migrate(&self, doc: &mut Value) -> Result<Option<u32>, (Option<u32>, String)> {
+        let key = self.version_key();
+        let table = doc
+            .as_table()
+            .ok_or_else(|| (None, "settings document is not a table".to_string()))?;
+        let version = match table.get(key) {
+            None => 1,
+            Some(Value::Integer(v)) if *v >= 1 && *v <= u32::MAX as i64 => *v as u32,
+            Some(other) => {
+                return Err((
+                    None,
+                    format!("invalid schema version `{}` under key `{}`", other, key),
+                ))
+            }
+        };
+        if version > self.current() {
+            return Err((
+                Some(version),
+                format!(
+                    "schema version {} is newer than supported version {}",
+                    version,
+                    self.current()
+                ),
+            ));
+        }
+        if version == self.current() {
+            return Ok(None);
+        }
+
+        for (from, step) in (version..).zip(&self.steps[version as usize - 1..]) {
+            step(doc).map_err(|message| (Some(from), message))?;
+        }
+        match doc {
+            Value::Table(table) => {
+                table.insert(key.to_string(), Value::Integer(self.current().into()));
+            }
+            _ => {
+                return Err((
+                    Some(self.current() - 1),
+                    "migration replaced the settings document with a non-table value".into(),
+                ))
+            }
+        }
+        Ok(Some(version))
+    }
+
+    /// Check that `data` stores the current version, so that saving it does not drop the version
+    /// and make the next load migrate the document again.
+    pub(crate) fn check<T: Serialize>(&self, data: &T) -> Result<(), String> {
+        if self.steps.is_empty() {
+            return Ok(());
+        }
+        let key = self.version_key();
+        let value = Value::try_from(data).map_err(|e| e.to_string())?;
+        match value.get(key) {
+            Some(Value::Integer(v)) if *v == i64::from(self.current()) => Ok(()),
+            Some(other) => Err(format!(
+                "settings store schema version `{}` under key `{}`, expected {}",
+                other,
+                key,
+                self.current()
+            )),
+            None => Err(format!(
+                "settings type has no `{}` field to store the schema version in",
+                key
+            )),
+        }
+    }
+}
+
+/// Path where the original file of schema version `version` is preserved after migration.
+pub(crate) fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(format!(".v{}.bak", version));
+    path.with_file_name(name)
+}
@@ -0,0 +1,233 @@
+//! Ephemeral overrides applied on top of loaded settings.
+
+use {
+    crate::{layer::lookup, Error},
+    std::env,
+    toml::{value::Table, Value},
+};
+
+/// Parse `raw` as a TOML literal such as `8080`, `true`, `"text"` or `[1, 2]`.
+pub(crate) fn parse_literal(raw: &str) -> Option<Value> {
+    let mut table = toml::from_str::<Table>(&format!("value = {}", raw)).ok()?;
+    let value = table.remove("value")?;
+    if table.is_empty() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "a string",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        Value::Datetime(_) => "a datetime",
+        Value::Array(_) => "an array",
+        Value::Table(_) => "a table",
+    }
+}
+
+/// Convert a raw string into a value of the same type as `current`, or guess the type if there is no current value.
+fn coerce(raw: &str, current: Option<&Value>) -> Result<Value, String> {
+    let mismatch = |current: &Value| format!("expected {}, got `{}`", type_name(current), raw);
+    match current {
+        None => Ok(parse_literal(raw).unwrap_or_else(|| Value::String(raw.to_string()))),
+        Some(Value::String(_)) => Ok(Value::String(raw.to_string())),
+        Some(current @ Value::Integer(_)) => raw
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| mismatch(current)),
+        Some(current @ Value::Float(_)) => {
+            raw.parse().map(Value::Float).map_err(|_| mismatch(current))
+        }
+        Some(current @ Value::Boolean(_)) => match raw.to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(Value::Boolean(true)),
+            "false" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(mismatch(current)),
+        },
+        Some(current @ Value::Datetime(_)) => raw
+            .parse()
+            .map(Value::Datetime)
+            .map_err(|_| mismatch(current)),
+        Some(current) => parse_literal(raw)
+            .filter(|value| value.same_type(current))
+            .ok_or_else(|| mismatch(current)),
+    }
+}
+
+/// Set a value at the dotted `path` inside `overrides`.
+/// `convert` receives the value currently found at `path` in `current` and produces the override.
+fn insert(
+    overrides: &mut Table,
+    current: Option<&Value>,
+    path: &[String],
+    source: &str,
+    convert: impl FnOnce(Option<&Value>) -> Result<Value, String>,
+) -> Result<(), Error> {
+    let key = path.join(".");
+    let error = |message: String| Error::Override {
+        key: key.clone(),
+        message: format!("{}: {}", source, message),
+    };
+
+    let mut table = overrides;
+    let mut current = current;
+    let (last, parents) = path.split_last().ok_or_else(|| error("empty key".into()))?;
+    for (i, part) in parents.iter().enumerate() {
+        current = current.and_then(|v| v.get(part.as_str()));
+        if let Some(value) = current.filter(|value| !value.is_table()) {
+            return Err(error(format!(
+                "`{}` is {}, not a table",
+                path[..=i].join("."),
+                type_name(value)
+            )));
+        }
+        table = match table
+            .entry(part.clone())
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(table) => table,
+            _ => return Err(error(format!("`{}` is not a table", path[..=i].join(".")))),
+        };
+    }
+    let value = convert(current.and_then(|v| v.get(last.as_str()))).map_err(error)?;
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+/// Overrides read from environment variables.
+///
+/// A variable named `<PREFIX>_SERVER__PORT` overrides the key `server.port`: the prefix and an underscore are stripped,
+/// the rest is split at the separator (`__` by default) and lowercased.
+/// Values are converted to the type of the value they override; new keys are parsed as TOML literals, falling back to strings.
+#[derive(Clone, Debug)]
+pub struct EnvOverrides {
+    prefix: String,
+    separator: String,
+}
+
+impl EnvOverrides {
+    /// Read variables starting with `prefix` followed by an underscore.
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            separator: "__".into(),
+        }
+    }
+
+    /// Use `separator` between nested keys instead of `__`.
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Collect overrides from the environment. `current` holds the settings being overridden.
+    pub(crate) fn collect(&self, current: &Value) -> Result<Value, Error> {
+        let prefix = format!("{}_", self.prefix);
+        let mut vars = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| name.starts_with(&prefix))
+            .collect::<Vec<_>>();
+        vars.sort();
+
+        let mut overrides = Table::new();
+        for (name, value) in vars {
+            let path = name[prefix.len()..]
+                .split(self.separator.as_str())
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>();
+            insert(
+                &mut overrides,
+                Some(current),
+                &path,
+                &format!("environment variable {}", name),
+                |current| coerce(&value, current),
+            )?;
+        }
+        Ok(Value::Table(overrides))
+    }
+}
+
+/// Convert a TOML literal given on the command line for a key whose current value is `current`.
+/// Keys without a current value, such as unset optional values, take the literal as is, or a string if it does not parse.
+fn convert_literal(raw: &str, current: Option<&Value>) -> Result<Value, String> {
+    let current = match current {
+        Some(current) => current,
+        None => return Ok(parse_literal(raw).unwrap_or_else(|| Value::String(raw.to_string()))),
+    };
+    match (parse_literal(raw), current) {
+        (Some(value), current) if value.same_type(current) => Ok(value),
+        (Some(Value::Integer(value)), Value::Float(_)) => Ok(Value::Float(value as f64)),
+        (_, Value::String(_)) => Ok(Value::String(raw.to_string())),
+        (Some(value), current) => Err(format!(
+            "expected {}, got {} `{}`",
+            type_name(current),
+            type_name(&value),
+            raw
+        )),
+        (None, _) => Err(format!("`{}` is not a valid TOML value", raw)),
+    }
+}
+
+/// Split a `key.path=value` assignment into the key path and the raw value.
+fn split_assignment(assignment: &str) -> Result<(Vec<String>, &str), Error> {
+    let source = format!("override `{}`", assignment);
+    let (key, raw) = assignment.split_once('=').ok_or_else(|| Error::Override {
+        key: assignment.trim().to_string(),
+        message: format!("{}: expected `key.path=value`", source),
+    })?;
+    let path = key
+        .trim()
+        .split('.')
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if path.iter().any(String::is_empty) {
+        return Err(Error::Override {
+            key: key.trim().to_string(),
+            message: format!("{}: empty key segment", source),
+        });
+    }
+    Ok((path, raw.trim()))
+}
+
+/// Parse `key.path=value` assignments into overrides, checking value types against `current`.
+/// Values are TOML literals; values for string keys may also be given unquoted.
+pub(crate) fn parse_assignments<I>(assignments: I, current: &Value) -> Result<Value, Error>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut overrides = Table::new();
+    for assignment in assignments {
+        let assignment = assignment.as_ref();
+        let (path, raw) = split_assignment(assignment)?;
+        let source = format!("override `{}`", assignment);
+        insert(&mut overrides, Some(current), &path, &source, |current| {
+            convert_literal(raw, current)
+        })?;
+    }
+    Ok(Value::Table(overrides))
+}
+
+/// Reject assignments to keys that `data`, the serialized settings with the overrides applied, does not have.
+/// Keys are checked only after deserializing, since unset optional values are missing from the current settings.
+pub(crate) fn check_keys<I>(assignments: I, data: &Value) -> Result<(), Error>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    for assignment in assignments {
+        let assignment = assignment.as_ref();
+        let (path, _) = split_assignment(assignment)?;
+        let key = path.join(".");
+        if lookup(data, &key).is_none() {
+            return Err(Error::Override {
+                key,
+                message: format!("override `{}`: unknown key", assignment),
+            });
+        }
+    }
+    Ok(())
+}
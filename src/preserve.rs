@@ -0,0 +1,99 @@
+//! Format-preserving TOML updates.
+
+use {
+    toml::{value::Table, Value},
+    toml_edit::{ArrayOfTables, DocumentMut, Item, TableLike},
+};
+
+/// Apply `new` to the TOML document `previous`, touching only keys whose values changed.
+/// Returns `None` if `previous` is not valid TOML.
+pub(crate) fn update(previous: &str, new: &Table) -> Option<String> {
+    let mut doc = previous.parse::<DocumentMut>().ok()?;
+    let old = toml::from_str::<Table>(previous).ok()?;
+    patch(doc.as_table_mut(), false, &old, new);
+    Some(doc.to_string())
+}
+
+fn patch(doc: &mut dyn TableLike, inline: bool, old: &Table, new: &Table) {
+    for key in old.keys() {
+        if !new.contains_key(key) {
+            doc.remove(key);
+        }
+    }
+    for (key, value) in new {
+        let old_value = old.get(key);
+        if old_value == Some(value) {
+            continue;
+        }
+        if let (Some(Value::Table(old_table)), Value::Table(new_table)) = (old_value, value) {
+            if let Some(item) = doc.get_mut(key) {
+                let inline = item.is_inline_table();
+                if let Some(table) = item.as_table_like_mut() {
+                    patch(table, inline, old_table, new_table);
+                    continue;
+                }
+            }
+        }
+
+        let previous = doc.get(key).and_then(Item::as_value);
+        let mut item = if inline || previous.is_some() {
+            Item::Value(to_value(value))
+        } else {
+            to_item(value)
+        };
+        if let (Some(new), Some(previous)) = (item.as_value_mut(), previous) {
+            *new.decor_mut() = previous.decor().clone();
+        }
+        match doc.get_mut(key) {
+            Some(existing) => *existing = item,
+            None => {
+                doc.insert(key, item);
+            }
+        }
+    }
+}
+
+fn to_item(value: &Value) -> Item {
+    match value {
+        Value::Table(table) => {
+            let mut out = toml_edit::Table::new();
+            for (key, value) in table {
+                out.insert(key, to_item(value));
+            }
+            Item::Table(out)
+        }
+        Value::Array(array) if !array.is_empty() && array.iter().all(Value::is_table) => {
+            let mut out = ArrayOfTables::new();
+            for table in array {
+                if let Item::Table(table) = to_item(table) {
+                    out.push(table);
+                }
+            }
+            Item::ArrayOfTables(out)
+        }
+        value => Item::Value(to_value(value)),
+    }
+}
+
+fn to_value(value: &Value) -> toml_edit::Value {
+    match value {
+        Value::String(s) => s.as_str().into(),
+        Value::Integer(i) => (*i).into(),
+        Value::Float(f) => (*f).into(),
+        Value::Boolean(b) => (*b).into(),
+        Value::Datetime(dt) => match dt.to_string().parse::<toml_edit::Datetime>() {
+            Ok(dt) => dt.into(),
+            Err(_) => dt.to_string().into(),
+        },
+        Value::Array(array) => array
+            .iter()
+            .map(to_value)
+            .collect::<toml_edit::Array>()
+            .into(),
+        Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| (key.as_str(), to_value(value)))
+            .collect::<toml_edit::InlineTable>()
+            .into(),
+    }
+}
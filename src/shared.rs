@@ -0,0 +1,374 @@
+//! Settings shared between threads.
+
+use {
+    crate::{
+        check_unchanged, lock, Auto, Borrow, Error, Format, LockMode, Pending, Reloader, Settings,
+        SettingsGuard, Subscription,
+    },
+    serde::{de::DeserializeOwned, Serialize},
+    std::{
+        ops::{Deref, DerefMut},
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+        },
+        thread,
+    },
+};
+
+pub(crate) struct Shared<T, F> {
+    settings: RwLock<Settings<T, F>>,
+    /// Number of changes handed over for saving so far.
+    changes: AtomicU64,
+    /// Serializes writes to the file and holds the number of the change last written.
+    written: Mutex<u64>,
+}
+
+/// Cloneable handle to settings shared between threads.
+///
+/// Readers share access to the data while a writer holds it exclusively.
+/// Changes are serialized while the data is locked, but the file is written after the lock is released,
+/// so readers are not blocked by disk I/O. If several changes are saved concurrently only the latest one is written.
+pub struct SharedSettings<T, F = Auto> {
+    pub(crate) shared: Arc<Shared<T, F>>,
+}
+
+impl<T, F> Clone for SharedSettings<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T, F> From<Settings<T, F>> for SharedSettings<T, F> {
+    fn from(settings: Settings<T, F>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                settings: RwLock::new(settings),
+                changes: AtomicU64::new(0),
+                written: Mutex::new(0),
+            }),
+        }
+    }
+}
+
+/// Read lock on shared settings, dereferencing to the data.
+struct ReadLock<'a, T, F>(RwLockReadGuard<'a, Settings<T, F>>);
+
+impl<'a, T, F> Deref for ReadLock<'a, T, F> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0.data
+    }
+}
+
+impl<T, F> SharedSettings<T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    /// Share `settings` between threads.
+    pub fn new(settings: Settings<T, F>) -> Self {
+        settings.into()
+    }
+
+    fn settings(&self) -> RwLockWriteGuard<'_, Settings<T, F>> {
+        self.shared.settings()
+    }
+
+    /// Lock configuration for read access, blocking while a writer holds it.
+    pub fn read(&self) -> SettingsGuard<'_, T> {
+        let lock = self
+            .shared
+            .settings
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        SettingsGuard {
+            data: Borrow::Locked(Box::new(ReadLock(lock))),
+        }
+    }
+
+    /// Lock configuration for mutable access, blocking while other threads hold it.
+    /// Data will be saved on disk upon guard's destruction.
+    pub fn write(&self) -> SharedSettingsGuard<'_, T, F> {
+        let settings = self.settings();
+        SharedSettingsGuard {
+            shared: &self.shared,
+            snapshot: settings.subscribers.snapshot(&settings.data),
+            settings: Some(settings),
+        }
+    }
+
+    /// Modify configuration with `f` and save it to disk, returning the closure's result.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, Error> {
+        let mut guard = self.write();
+        let r = f(&mut guard);
+        guard.commit()?;
+        Ok(r)
+    }
+
+    /// Reload the file if it was modified since it was last read or written. See [`Settings::reload_if_changed`].
+    pub fn reload_if_changed(&self) -> Result<bool, Error> {
+        self.settings().reload_if_changed()
+    }
+
+    /// Reload the file in the background whenever another program changes it, until the returned [`Reloader`] is dropped.
+    ///
+    /// Contents that fail to parse or validate are reported to the hook set with [`Settings::set_error_hook`]
+    /// and the current data is kept.
+    pub fn reload_on_change(&self) -> Reloader
+    where
+        T: Send + Sync + 'static,
+        F: Send + Sync + 'static,
+    {
+        let path = self.settings().path.clone();
+        let shared = Arc::downgrade(&self.shared);
+        Reloader::spawn(&path, move || match shared.upgrade() {
+            Some(shared) => {
+                let res = shared.settings().reload_if_changed();
+                if let Err(e) = res {
+                    shared.report(&e);
+                }
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Whether a panic left the data in a state that could not be restored. See [`Settings::is_poisoned`].
+    pub fn is_poisoned(&self) -> bool {
+        self.settings().is_poisoned()
+    }
+
+    /// Allow saving the current data again after it was poisoned by a panic.
+    pub fn clear_poison(&self) {
+        self.settings().clear_poison()
+    }
+
+    /// Call `callback` with the old and new data after every change. See [`Settings::subscribe`].
+    pub fn subscribe(&self, callback: impl Fn(&T, &T) + Send + Sync + 'static) -> Subscription
+    where
+        T: Clone,
+    {
+        self.settings().subscribe(callback)
+    }
+
+    /// Call `callback` after changes to the value at a dotted key path. See [`Settings::subscribe_path`].
+    pub fn subscribe_path(
+        &self,
+        path: impl Into<String>,
+        callback: impl Fn(&T, &T) + Send + Sync + 'static,
+    ) -> Subscription
+    where
+        T: Clone,
+    {
+        self.settings().subscribe_path(path, callback)
+    }
+
+    /// Call `callback` after changes to the field picked by `select`. See [`Settings::subscribe_field`].
+    pub fn subscribe_field<V>(
+        &self,
+        select: impl Fn(&T) -> &V + Send + Sync + 'static,
+        callback: impl Fn(&V, &V) + Send + Sync + 'static,
+    ) -> Subscription
+    where
+        T: Clone,
+        V: PartialEq + ?Sized,
+    {
+        self.settings().subscribe_field(select, callback)
+    }
+
+    /// Cancel a subscription, returning whether it was active.
+    pub fn unsubscribe(&self, subscription: Subscription) -> bool {
+        self.settings().unsubscribe(subscription)
+    }
+}
+
+/// Guard for mutable access to [`SharedSettings`]. Persists to disk upon destruction.
+///
+/// Use [`SharedSettingsGuard::commit`] to save explicitly and handle failures.
+/// Errors from the implicit save on destruction are reported to the hook set with [`Settings::set_error_hook`].
+pub struct SharedSettingsGuard<'a, T, F = Auto>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    pub(crate) shared: &'a Arc<Shared<T, F>>,
+    /// Write lock, released once the data has been serialized.
+    settings: Option<RwLockWriteGuard<'a, Settings<T, F>>>,
+    /// Copy of the data taken for subscribers, restored if the changes are discarded. Without it the file is read again.
+    snapshot: Option<T>,
+}
+
+impl<'a, T, F> SharedSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    /// Save the data to disk, consuming the guard.
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.save()
+    }
+
+    fn save(&mut self) -> Result<(), Error> {
+        match self.detach()? {
+            Some(write) => write.write(),
+            None => Ok(()),
+        }
+    }
+
+    /// Serialize the data and release the lock, returning the write still to be performed.
+    /// Returns `None` if the data was already saved.
+    pub(crate) fn detach(&mut self) -> Result<Option<PendingWrite<T, F>>, Error> {
+        let mut settings = match self.settings.take() {
+            Some(settings) => settings,
+            None => return Ok(None),
+        };
+        let pending = match settings.prepare() {
+            Ok(pending) => pending,
+            Err(e) => {
+                if let Error::Validation(_) = e {
+                    // Changes rejected by validation are discarded.
+                    let _ = settings.restore(self.snapshot.take());
+                }
+                return Err(e);
+            }
+        };
+        let digest = pending.digest();
+        if settings.saved == Some(digest) {
+            return Ok(None);
+        }
+        // Recorded before writing so that a later change back to the saved data is not skipped as unchanged.
+        settings.saved = Some(digest);
+        Ok(Some(PendingWrite {
+            shared: self.shared.clone(),
+            change: self.shared.changes.fetch_add(1, Ordering::SeqCst) + 1,
+            path: settings.path.clone(),
+            lock_mode: settings.lock_mode,
+            pending,
+            snapshot: self.snapshot.take(),
+            done: false,
+        }))
+    }
+}
+
+impl<'a, T, F> Deref for SharedSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.settings.as_ref().expect("guard is locked").data
+    }
+}
+
+impl<'a, T, F> DerefMut for SharedSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.settings.as_mut().expect("guard is locked").data
+    }
+}
+
+impl<'a, T, F> Drop for SharedSettingsGuard<'a, T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    fn drop(&mut self) {
+        match &mut self.settings {
+            // Changes interrupted by a panic may be incomplete, so they are discarded instead of saved.
+            Some(settings) if thread::panicking() => {
+                if settings.restore(self.snapshot.take()).is_err() {
+                    settings.poisoned = true;
+                }
+            }
+            _ => {
+                if let Err(e) = self.save() {
+                    self.settings = None;
+                    self.shared.report(&e);
+                }
+            }
+        }
+    }
+}
+
+impl<T, F> Shared<T, F> {
+    fn settings(&self) -> RwLockWriteGuard<'_, Settings<T, F>> {
+        self.settings
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Pass an error that cannot be returned to the error hook.
+    pub(crate) fn report(&self, e: &Error) {
+        let settings = self.settings.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(hook) = &settings.error_hook {
+            hook(e);
+        }
+    }
+}
+
+/// Serialized change to shared settings that still has to be written to disk.
+pub(crate) struct PendingWrite<T, F> {
+    pub shared: Arc<Shared<T, F>>,
+    /// Number of the change, used to skip writes superseded by newer ones.
+    change: u64,
+    pub path: PathBuf,
+    lock_mode: LockMode,
+    pending: Pending,
+    /// Data before the change, passed to subscribers once it is written.
+    snapshot: Option<T>,
+    /// Whether the change was written or superseded by a newer one.
+    done: bool,
+}
+
+impl<T, F> PendingWrite<T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    /// Write the change unless a newer one has already been written, then notify subscribers.
+    pub(crate) fn write(mut self) -> Result<(), Error> {
+        let mut written = self
+            .shared
+            .written
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if *written > self.change {
+            // The newer change was saved with this one included.
+            self.shared.settings().notify(self.snapshot.as_ref());
+            self.done = true;
+            return Ok(());
+        }
+        let (stamp, contents) = {
+            let settings = self.shared.settings();
+            (settings.stamp, settings.contents)
+        };
+        lock::acquire(&self.path, self.lock_mode, true).and_then(|_lock| {
+            check_unchanged(&self.path, stamp, contents)?;
+            self.pending.write(&self.path)
+        })?;
+        *written = self.change;
+        let digest = self.pending.digest();
+        let mut settings = self.shared.settings();
+        settings.finish(self.pending.file.take(), digest);
+        settings.notify(self.snapshot.as_ref());
+        self.done = true;
+        Ok(())
+    }
+}
+
+impl<T, F> Drop for PendingWrite<T, F> {
+    fn drop(&mut self) {
+        if !self.done {
+            // Let the next save retry instead of skipping the same data as unchanged.
+            self.shared.settings().saved = None;
+        }
+    }
+}
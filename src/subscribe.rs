@@ -0,0 +1,98 @@
+//! Notification of other components when settings change.
+
+use {crate::layer, serde::Serialize, std::cell::OnceCell};
+
+/// Handle to a change subscription, used to cancel it with [`Settings::unsubscribe`](crate::Settings::unsubscribe).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Subscription(u64);
+
+type Callback<T> = dyn Fn(&Change<'_, T>) + Send + Sync;
+
+pub(crate) struct Subscribers<T> {
+    next: u64,
+    callbacks: Vec<(Subscription, Box<Callback<T>>)>,
+    /// Copies the data before a change, so that subscribers can be passed the old side.
+    clone: Option<fn(&T) -> T>,
+}
+
+impl<T> Default for Subscribers<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            callbacks: Vec::new(),
+            clone: None,
+        }
+    }
+}
+
+impl<T> Subscribers<T> {
+    pub fn add(
+        &mut self,
+        clone: fn(&T) -> T,
+        callback: impl Fn(&Change<'_, T>) + Send + Sync + 'static,
+    ) -> Subscription {
+        let subscription = Subscription(self.next);
+        self.next += 1;
+        self.callbacks.push((subscription, Box::new(callback)));
+        self.clone = Some(clone);
+        subscription
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    /// Copy of `data` to pass to subscribers as the old side of a change, if there are any.
+    pub fn snapshot(&self, data: &T) -> Option<T> {
+        match self.clone {
+            Some(clone) if !self.is_empty() => Some(clone(data)),
+            _ => None,
+        }
+    }
+
+    /// Cancel `subscription`, returning whether it was active.
+    pub fn remove(&mut self, subscription: Subscription) -> bool {
+        let len = self.callbacks.len();
+        self.callbacks.retain(|(s, _)| *s != subscription);
+        self.callbacks.len() != len
+    }
+
+    /// Pass the change from `old` to `new` to every subscriber, which decides whether it is relevant.
+    pub fn notify(&self, old: &T, new: &T) {
+        let change = Change {
+            old,
+            new,
+            values: OnceCell::new(),
+        };
+        for (_, callback) in &self.callbacks {
+            callback(&change);
+        }
+    }
+}
+
+/// Data before and after a change.
+pub(crate) struct Change<'a, T> {
+    pub old: &'a T,
+    pub new: &'a T,
+    /// Both sides converted to TOML values, computed once for all path subscriptions.
+    values: OnceCell<Option<(toml::Value, toml::Value)>>,
+}
+
+impl<'a, T> Change<'a, T>
+where
+    T: Serialize,
+{
+    /// Whether the value at dotted `path` differs. Data that cannot be converted counts as changed.
+    pub fn path_changed(&self, path: &str) -> bool {
+        let values = self.values.get_or_init(|| {
+            Some((
+                toml::Value::try_from(self.old).ok()?,
+                toml::Value::try_from(self.new).ok()?,
+            ))
+        });
+        match values {
+            Some((old, new)) => layer::lookup(old, path) != layer::lookup(new, path),
+            None => true,
+        }
+    }
+}
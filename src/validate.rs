@@ -0,0 +1,104 @@
+//! Validation of settings before they are accepted or saved.
+
+use std::{error::Error as StdError, fmt, slice};
+
+/// Settings that can check their own consistency, such as value ranges and required fields.
+///
+/// Enable it with [`Settings::enable_validation`](crate::Settings::enable_validation) or [`Settings::load_validated`](crate::Settings::load_validated).
+/// With the `derive` feature it can be derived from per-field constraints such as `#[setting(range = 1..=65535)]`.
+pub trait Validate {
+    /// Check the settings, naming every offending field by its dotted path.
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// A field rejected by validation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    /// Dotted path to the field, such as `server.port`. Empty for errors about the settings as a whole.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "`{}`: {}", self.path, self.message)
+        }
+    }
+}
+
+/// All fields rejected by validation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject the field at dotted `path`.
+    pub fn add(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    /// Add the errors from validating a nested value stored under `prefix`, prefixing their paths.
+    /// An empty prefix adds the errors unchanged.
+    pub fn nest(&mut self, prefix: &str, result: Result<(), ValidationErrors>) {
+        if let Err(nested) = result {
+            for mut error in nested.errors {
+                if error.path.is_empty() {
+                    error.path = prefix.to_string();
+                } else if !prefix.is_empty() {
+                    error.path = format!("{}.{}", prefix, error.path);
+                }
+                self.errors.push(error);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, FieldError> {
+        self.errors.iter()
+    }
+
+    /// `Ok` if no errors were added, the errors otherwise.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ValidationErrors {
+    type Item = &'a FieldError;
+    type IntoIter = slice::Iter<'a, FieldError>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for ValidationErrors {}
@@ -0,0 +1,184 @@
+//! Detection of external changes to settings files.
+
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Identity of a file's contents as far as metadata can tell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    #[cfg(unix)]
+    inode: u64,
+}
+
+impl FileStamp {
+    /// Stamp of the file at `path`, or `None` if it cannot be read.
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            #[cfg(unix)]
+            inode: std::os::unix::fs::MetadataExt::ino(&metadata),
+        })
+    }
+}
+
+/// Default interval for polling watchers.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches a settings file for changes made by other programs.
+///
+/// The watcher only signals that the file may have changed; call [`Settings::reload_if_changed`](crate::Settings::reload_if_changed)
+/// to pick up the new contents, or use a [`Reloader`] to do so in the background for shared settings.
+/// With the `watch` feature the platform's file notification API such as inotify is used,
+/// otherwise and if it is unavailable the file is polled.
+pub struct Watcher {
+    rx: mpsc::Receiver<()>,
+    /// Keeps the notification watcher alive.
+    #[cfg(feature = "watch")]
+    _notify: Option<notify::RecommendedWatcher>,
+    /// Stops the polling thread.
+    stop: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    /// Watch the file at `path`, using file notifications if available.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        #[cfg(feature = "watch")]
+        {
+            if let Ok(watcher) = Self::notify(path.as_ref()) {
+                return watcher;
+            }
+        }
+        Self::polling(path, POLL_INTERVAL)
+    }
+
+    #[cfg(feature = "watch")]
+    fn notify(path: &Path) -> notify::Result<Self> {
+        use {notify::Watcher as _, std::ffi::OsString};
+
+        let (tx, rx) = mpsc::channel();
+        let name = path.file_name().map(OsString::from);
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let relevant = match event {
+                    Ok(event) => event
+                        .paths
+                        .iter()
+                        .any(|changed| changed.file_name() == name.as_deref()),
+                    Err(_) => true,
+                };
+                if relevant {
+                    let _ = tx.send(());
+                }
+            })?;
+        // Saves replace the file by renaming, so the directory is watched rather than the file itself.
+        watcher.watch(&parent(path), notify::RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            rx,
+            _notify: Some(watcher),
+            stop: Arc::default(),
+        })
+    }
+
+    /// Watch the file at `path` by checking its metadata every `interval`.
+    pub fn polling(path: impl AsRef<Path>, interval: Duration) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let path = path.as_ref().to_path_buf();
+        let stopped = stop.clone();
+        // Taken before the thread starts, so that no change made after this call is missed.
+        let mut stamp = FileStamp::of(&path);
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+                let current = FileStamp::of(&path);
+                if current != stamp {
+                    stamp = current;
+                    if tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Self {
+            rx,
+            #[cfg(feature = "watch")]
+            _notify: None,
+            stop,
+        }
+    }
+
+    /// Whether the file may have changed since the last call. Does not block.
+    pub fn changed(&self) -> bool {
+        self.rx.try_iter().count() > 0
+    }
+
+    /// Block until the file may have changed.
+    pub fn wait(&self) {
+        let _ = self.rx.recv();
+        self.changed();
+    }
+
+    /// Block until the file may have changed or `timeout` elapses. Returns whether a change was seen.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let changed = self.rx.recv_timeout(timeout).is_ok();
+        self.changed() || changed
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Reloads shared settings in the background whenever their file changes, until it is dropped.
+///
+/// Created by [`SharedSettings::reload_on_change`](crate::SharedSettings::reload_on_change).
+pub struct Reloader {
+    stop: Arc<AtomicBool>,
+}
+
+impl Reloader {
+    /// Call `reload` on a background thread whenever the file at `path` may have changed.
+    /// The thread exits once the reloader is dropped or `reload` returns `false`.
+    pub(crate) fn spawn(path: &Path, mut reload: impl FnMut() -> bool + Send + 'static) -> Self {
+        // Created before the thread starts, so that no change made after this call is missed.
+        let watcher = Watcher::new(path);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                if watcher.wait_timeout(POLL_INTERVAL) && !reload() {
+                    break;
+                }
+            }
+        });
+        Self { stop }
+    }
+}
+
+impl Drop for Reloader {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "watch")]
+fn parent(path: &Path) -> std::path::PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => ".".into(),
+    }
+}
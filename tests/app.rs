@@ -0,0 +1,79 @@
+#![cfg(unix)]
+
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{AppDirs, AppSettings, Error},
+    std::env,
+};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Config {
+    count: u32,
+}
+
+const VARS: &[&str] = &[
+    "HOME",
+    "XDG_CONFIG_HOME",
+    "XDG_STATE_HOME",
+    "XDG_CACHE_HOME",
+    "XDG_DATA_HOME",
+];
+
+fn clear() {
+    for var in VARS {
+        env::remove_var(var);
+    }
+}
+
+// The environment is process-wide, so all cases run in one test.
+#[test]
+fn app_dirs_from_environment() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let home = root.join("home");
+
+    clear();
+    env::set_var("HOME", &home);
+    let dirs = AppDirs::new("org", "app");
+    assert_eq!(dirs.config_dir(), Some(&*root.join("home/.config/app")));
+    assert_eq!(dirs.state_dir(), Some(&*root.join("home/.local/state/app")));
+    assert_eq!(dirs.cache_dir(), Some(&*root.join("home/.cache/app")));
+    assert_eq!(dirs.data_dir(), Some(&*root.join("home/.local/share/app")));
+
+    env::set_var("XDG_CONFIG_HOME", root.join("config"));
+    env::set_var("XDG_STATE_HOME", root.join("state"));
+    env::set_var("XDG_CACHE_HOME", root.join("cache"));
+    env::set_var("XDG_DATA_HOME", root.join("data"));
+    let dirs = AppDirs::new("org", "app");
+    assert_eq!(dirs.config_dir(), Some(&*root.join("config/app")));
+    assert_eq!(dirs.state_dir(), Some(&*root.join("state/app")));
+    assert_eq!(dirs.cache_dir(), Some(&*root.join("cache/app")));
+    assert_eq!(dirs.data_dir(), Some(&*root.join("data/app")));
+
+    // Relative paths are ignored.
+    env::set_var("XDG_CONFIG_HOME", "relative/config");
+    let dirs = AppDirs::new("org", "app");
+    assert_eq!(dirs.config_dir(), Some(&*root.join("home/.config/app")));
+
+    // Explicit directories do not need a home directory, and the others stay unknown.
+    clear();
+    env::set_var("XDG_CONFIG_HOME", root.join("config"));
+    let dirs = AppDirs::new("org", "app");
+    assert_eq!(dirs.config_dir(), Some(&*root.join("config/app")));
+    assert_eq!(dirs.state_dir(), None);
+    assert_eq!(dirs.cache_dir(), None);
+    assert_eq!(dirs.data_dir(), None);
+    let settings = AppSettings::<Config>::open("org", "app", "settings.toml").unwrap();
+    assert_eq!(settings.guard().count, 0);
+    assert!(root.join("config/app/settings.toml").is_file());
+
+    clear();
+    assert_eq!(AppDirs::new("org", "app").config_dir(), None);
+    assert!(matches!(
+        AppSettings::<Config>::open("org", "app", "settings.toml"),
+        Err(Error::NoHomeDir)
+    ));
+    env::set_var("HOME", "relative/home");
+    assert_eq!(AppDirs::new("org", "app").config_dir(), None);
+    assert!(!home.exists());
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "async")]
+
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{AsyncSettings, Settings},
+    std::{fs, path::Path},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    a: u32,
+    b: u32,
+}
+
+fn saved(path: &Path) -> Config {
+    Settings::<Config>::load(path)
+        .unwrap()
+        .unwrap()
+        .guard()
+        .clone()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spawned_updates() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let settings = AsyncSettings::new(&path, Config::default()).await.unwrap();
+
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            let settings = settings.clone();
+            tokio::spawn(async move { settings.update(|c| c.a += 1).await })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+
+    let commit = {
+        let mut guard = settings.write();
+        guard.b = 5;
+        guard.commit()
+    };
+    tokio::spawn(commit).await.unwrap().unwrap();
+
+    assert_eq!(*settings.read(), Config { a: 8, b: 5 });
+    assert_eq!(saved(&path), Config { a: 8, b: 5 });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dropped_commit_is_saved_later() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let settings = AsyncSettings::new(&path, Config::default()).await.unwrap();
+
+    let commit = {
+        let mut guard = settings.write();
+        guard.a = 1;
+        guard.commit()
+    };
+    drop(commit);
+    assert_eq!(saved(&path), Config::default());
+
+    settings.update(|_| ()).await.unwrap();
+    assert_eq!(saved(&path), Config { a: 1, b: 0 });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn load_and_reload() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    assert!(AsyncSettings::<Config>::load(&path)
+        .await
+        .unwrap()
+        .is_none());
+    let (settings, _) = AsyncSettings::<Config>::load_or_default(&path)
+        .await
+        .unwrap();
+
+    fs::write(&path, "a = 10\nb = 20\n").unwrap();
+    assert!(settings.reload_if_changed().await.unwrap());
+    assert_eq!(*settings.read(), Config { a: 10, b: 20 });
+    let loaded = AsyncSettings::<Config>::load(&path).await.unwrap().unwrap();
+    assert_eq!(*loaded.read(), Config { a: 10, b: 20 });
+}
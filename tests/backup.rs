@@ -0,0 +1,157 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Backups, Settings, Toml},
+    std::{fs, path::Path},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    count: u32,
+}
+
+fn count_in(path: &Path) -> u32 {
+    Settings::<Config, Toml>::load_with_format(path, Toml)
+        .unwrap()
+        .unwrap()
+        .guard()
+        .count
+}
+
+#[test]
+fn numbered_backups_rotate_up_to_keep() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.set_backups(Backups::numbered(3));
+
+    for count in 1..=5 {
+        settings.update(|c| c.count = count).unwrap();
+    }
+    // Unchanged saves take no backup.
+    settings.update(|_| ()).unwrap();
+
+    let backups = settings.list_backups().unwrap();
+    assert_eq!(
+        backups,
+        [1, 2, 3].map(|n| dir.path().join(format!("settings.toml.{}", n)))
+    );
+    let counts: Vec<_> = backups.iter().map(|b| count_in(b)).collect();
+    assert_eq!(counts, [4, 3, 2]);
+    assert!(!dir.path().join("settings.toml.4").exists());
+}
+
+#[test]
+fn numbered_backups_close_gaps() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config { count: 10 }).unwrap();
+    fs::write(dir.path().join("settings.toml.1"), "count = 1\n").unwrap();
+    fs::write(dir.path().join("settings.toml.4"), "count = 4\n").unwrap();
+    settings.set_backups(Backups::numbered(3));
+
+    settings.update(|c| c.count = 11).unwrap();
+
+    let counts: Vec<_> = settings
+        .list_backups()
+        .unwrap()
+        .iter()
+        .map(|b| count_in(b))
+        .collect();
+    assert_eq!(counts, [10, 1, 4]);
+    assert!(dir.path().join("settings.toml.3").exists());
+    assert!(!dir.path().join("settings.toml.4").exists());
+}
+
+#[test]
+fn timestamped_backups_are_pruned() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let backup_dir = dir.path().join("backups");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.set_backups(Backups::timestamped(&backup_dir, 2));
+
+    for count in 1..=4 {
+        settings.update(|c| c.count = count).unwrap();
+    }
+
+    let backups = settings.list_backups().unwrap();
+    assert_eq!(fs::read_dir(&backup_dir).unwrap().count(), 2);
+    let counts: Vec<_> = backups.iter().map(|b| count_in(b)).collect();
+    assert_eq!(counts, [3, 2]);
+}
+
+#[test]
+fn timestamped_backups_in_quick_succession_are_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let backup_dir = dir.path().join("backups");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.set_backups(Backups::timestamped(&backup_dir, 20));
+
+    for count in 1..=12 {
+        settings.update(|c| c.count = count).unwrap();
+    }
+
+    let counts: Vec<_> = settings
+        .list_backups()
+        .unwrap()
+        .iter()
+        .map(|b| count_in(b))
+        .collect();
+    assert_eq!(counts, (0..12).rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn timestamped_backups_sort_by_time_then_counter() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let backup_dir = dir.path().join("backups");
+    fs::create_dir(&backup_dir).unwrap();
+    for suffix in [
+        "20240101T000000.000Z-10",
+        "20240101T000000.001Z",
+        "20240101T000000.000Z",
+        "20240101T000000.000Z-2",
+        "20240101T000000.000Z-x",
+    ] {
+        fs::write(backup_dir.join(format!("settings.toml.{}", suffix)), "").unwrap();
+    }
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.set_backups(Backups::timestamped(&backup_dir, 10));
+
+    let names: Vec<_> = settings
+        .list_backups()
+        .unwrap()
+        .iter()
+        .map(|b| b.file_name().unwrap().to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "settings.toml.20240101T000000.001Z",
+            "settings.toml.20240101T000000.000Z-10",
+            "settings.toml.20240101T000000.000Z-2",
+            "settings.toml.20240101T000000.000Z",
+        ]
+    );
+}
+
+#[test]
+fn restore_backup_replaces_data_and_keeps_current() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.set_backups(Backups::numbered(5));
+    for count in 1..=3 {
+        settings.update(|c| c.count = count).unwrap();
+    }
+
+    settings.restore_backup(2).unwrap();
+
+    assert_eq!(settings.guard().count, 1);
+    assert_eq!(count_in(&path), 1);
+    // The replaced file became the newest backup.
+    assert_eq!(count_in(&settings.list_backups().unwrap()[0]), 3);
+    assert!(settings.restore_backup(0).is_err());
+    assert!(settings.restore_backup(10).is_err());
+}
@@ -0,0 +1,152 @@
+#![cfg(feature = "derive")]
+
+use {
+    serde::Serialize,
+    simple_settings::{FieldError, Validate, ValidationErrors},
+};
+
+fn not_root(user: &str) -> Result<(), &'static str> {
+    if user == "root" {
+        Err("must not be root")
+    } else {
+        Ok(())
+    }
+}
+
+fn ports_differ(c: &Config) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if c.server.port == c.admin_port {
+        errors.add("adminPort", "must differ from server.port");
+    }
+    errors.into_result()
+}
+
+#[derive(Serialize, Validate)]
+struct Server {
+    #[setting(range = 1..=65535)]
+    port: u32,
+    #[setting(non_empty, regex = "^[a-z.]+$")]
+    host: String,
+}
+
+#[derive(Serialize, Validate)]
+#[serde(rename_all = "camelCase")]
+#[setting(check = "ports_differ")]
+struct Config {
+    #[setting(one_of = ["debug", "info"])]
+    log_level: String,
+    #[setting(range = ..100)]
+    max_retries: u32,
+    #[serde(rename = "user")]
+    #[setting(check = "not_root")]
+    run_as: String,
+    admin_port: u32,
+    #[setting(nested)]
+    server: Server,
+}
+
+fn valid() -> Config {
+    Config {
+        log_level: "info".into(),
+        max_retries: 3,
+        run_as: "app".into(),
+        admin_port: 8081,
+        server: Server {
+            port: 8080,
+            host: "example.org".into(),
+        },
+    }
+}
+
+fn errors(config: &Config) -> Vec<(String, String)> {
+    match config.validate() {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .iter()
+            .map(|FieldError { path, message }| (path.clone(), message.clone()))
+            .collect(),
+    }
+}
+
+fn error(path: &str, message: &str) -> Vec<(String, String)> {
+    vec![(path.to_string(), message.to_string())]
+}
+
+#[test]
+fn valid_settings_pass() {
+    assert_eq!(errors(&valid()), []);
+}
+
+#[test]
+fn range() {
+    let mut config = valid();
+    config.max_retries = 100;
+    assert_eq!(
+        errors(&config),
+        error("maxRetries", "must be less than 100")
+    );
+}
+
+#[test]
+fn non_empty_and_regex() {
+    let mut config = valid();
+    config.server.host = String::new();
+    assert_eq!(
+        errors(&config),
+        [
+            ("server.host".to_string(), "must not be empty".to_string()),
+            (
+                "server.host".to_string(),
+                "must match `^[a-z.]+$`".to_string()
+            ),
+        ]
+    );
+    config.server.host = "Example.org".into();
+    assert_eq!(
+        errors(&config),
+        error("server.host", "must match `^[a-z.]+$`")
+    );
+}
+
+#[test]
+fn one_of() {
+    let mut config = valid();
+    config.log_level = "trace".into();
+    assert_eq!(
+        errors(&config),
+        error("logLevel", "must be one of \"debug\", \"info\"")
+    );
+}
+
+#[test]
+fn field_check() {
+    let mut config = valid();
+    config.run_as = "root".into();
+    assert_eq!(errors(&config), error("user", "must not be root"));
+}
+
+#[test]
+fn nested() {
+    let mut config = valid();
+    config.server.port = 0;
+    assert_eq!(
+        errors(&config),
+        error("server.port", "must be between 1 and 65535")
+    );
+}
+
+#[test]
+fn struct_check() {
+    let mut config = valid();
+    config.admin_port = config.server.port;
+    assert_eq!(
+        errors(&config),
+        error("adminPort", "must differ from server.port")
+    );
+}
+
+#[test]
+fn compile_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
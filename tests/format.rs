@@ -0,0 +1,168 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{format, Auto, Error, Format, Settings},
+    std::fs,
+};
+
+#[cfg(feature = "bincode")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    a: u32,
+    b: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Document {
+    name: String,
+    ratio: f64,
+    enabled: bool,
+    tags: Vec<String>,
+    limit: Option<u32>,
+    server: Server,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Server {
+    host: String,
+    port: u16,
+}
+
+fn document() -> Document {
+    Document {
+        name: "test".into(),
+        ratio: 0.5,
+        enabled: true,
+        tags: vec!["a".into(), "b".into()],
+        limit: Some(10),
+        server: Server {
+            host: "localhost".into(),
+            port: 8080,
+        },
+    }
+}
+
+/// Save a document to `file`, check that it is stored in `format` and load it back.
+fn round_trip(file: &str, format: impl Format) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(file);
+    Settings::new(&path, document()).unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(format.decode::<Document>(&bytes).unwrap(), document());
+    let loaded = Settings::<Document>::load(&path).unwrap().unwrap();
+    assert_eq!(*loaded.guard(), document());
+}
+
+#[test]
+fn toml_round_trip() {
+    round_trip("settings.toml", format::Toml);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_round_trip() {
+    round_trip("settings.json", format::Json);
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn yaml_round_trip() {
+    round_trip("settings.yaml", format::Yaml);
+}
+
+#[cfg(feature = "ron")]
+#[test]
+fn ron_round_trip() {
+    round_trip("settings.ron", format::Ron);
+}
+
+#[cfg(feature = "json5")]
+#[test]
+fn json5_round_trip() {
+    round_trip("settings.json5", format::Json5);
+}
+
+#[cfg(feature = "bincode")]
+#[test]
+fn bincode_round_trip() {
+    let format = format::Bincode { schema: 1 };
+    let bytes = format.encode(&Config { a: 1, b: 2 }).unwrap();
+    assert_eq!(
+        format.decode::<Config>(&bytes).unwrap(),
+        Config { a: 1, b: 2 }
+    );
+}
+
+#[cfg(feature = "bincode")]
+#[test]
+fn bincode_rejects_trailing_bytes() {
+    let format = format::Bincode { schema: 1 };
+    let mut bytes = format.encode(&Config { a: 1, b: 2 }).unwrap();
+    bytes.extend_from_slice(&[0, 0, 0, 0]);
+    let e = format.decode::<Config>(&bytes).unwrap_err();
+    assert!(e.message.contains("unexpected bytes"), "{}", e);
+}
+
+#[test]
+fn extension_picks_format() {
+    assert_eq!(Auto::from_extension("toml"), Some(Auto::Toml));
+    assert_eq!(Auto::from_extension("TOML"), Some(Auto::Toml));
+    assert_eq!(Auto::from_extension("ini"), None);
+    #[cfg(feature = "yaml")]
+    assert_eq!(Auto::from_extension("yml"), Some(Auto::Yaml));
+    #[cfg(not(feature = "json"))]
+    assert_eq!(Auto::from_extension("json"), None);
+}
+
+#[test]
+fn unknown_extension_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.ini");
+
+    match Settings::new(&path, document()) {
+        Err(Error::UnknownFormat { path: p }) => assert_eq!(p, path),
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+    assert!(!path.exists());
+}
+
+#[test]
+fn file_without_extension_defaults_to_toml() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings");
+    Settings::new(&path, document()).unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(format::Toml.decode::<Document>(&bytes).unwrap(), document());
+    assert_eq!(Auto::sniff(&bytes), Some(Auto::Toml));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn file_without_extension_is_sniffed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings");
+    fs::write(&path, format::Json.encode(&document()).unwrap()).unwrap();
+
+    let mut settings = Settings::<Document>::load(&path).unwrap().unwrap();
+    assert_eq!(*settings.guard(), document());
+    settings.update(|d| d.server.port = 9090).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(Auto::sniff(&bytes), Some(Auto::Json));
+    assert_eq!(
+        format::Json.decode::<Document>(&bytes).unwrap().server.port,
+        9090
+    );
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn binary_header_is_sniffed() {
+    let bytes = format::Cbor::default().encode(&document()).unwrap();
+    assert_eq!(Auto::sniff(&bytes), Some(Auto::Cbor));
+}
+
+#[test]
+fn unparsable_contents_are_not_sniffed() {
+    assert_eq!(Auto::sniff(b"[[[ not settings"), None);
+}
@@ -0,0 +1,144 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Error, Settings, ValidationErrors},
+    std::{
+        fs,
+        sync::{Arc, Mutex},
+    },
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    count: u32,
+    #[serde(skip)]
+    cache: Vec<u32>,
+}
+
+#[test]
+fn rollback_restores_skipped_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.guard_mut().cache = vec![1, 2, 3];
+
+    let mut guard = settings.transaction();
+    guard.count = 5;
+    guard.cache.clear();
+    guard.rollback().unwrap();
+
+    assert_eq!(settings.guard().count, 0);
+    assert_eq!(settings.guard().cache, [1, 2, 3]);
+}
+
+#[test]
+fn rejected_changes_restore_skipped_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.guard_mut().cache = vec![1, 2, 3];
+    settings
+        .set_validator(|c: &Config| {
+            let mut errors = ValidationErrors::new();
+            if c.count > 10 {
+                errors.add("count", "must be at most 10");
+            }
+            errors.into_result()
+        })
+        .unwrap();
+
+    let res = settings.try_update(|c| {
+        c.count = 11;
+        c.cache.clear();
+        Ok::<_, Error>(())
+    });
+
+    assert!(matches!(res, Err(Error::Validation(_))));
+    assert_eq!(
+        *settings.guard(),
+        Config {
+            count: 0,
+            cache: vec![1, 2, 3],
+        }
+    );
+}
+
+#[test]
+fn subscribers_receive_skipped_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.guard_mut().cache = vec![1, 2, 3];
+    let old_cache = Arc::new(Mutex::new(None));
+    let seen = old_cache.clone();
+    settings.subscribe(move |old, _| *seen.lock().unwrap() = Some(old.cache.clone()));
+
+    settings.update(|c| c.count = 1).unwrap();
+
+    assert_eq!(*old_cache.lock().unwrap(), Some(vec![1, 2, 3]));
+}
+
+/// A table before a plain value cannot be written as TOML.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Unencodable {
+    table: Config,
+    value: u32,
+}
+
+#[test]
+fn rollback_does_not_depend_on_the_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::write(&path, "value = 1\n[table]\ncount = 2\n").unwrap();
+    let mut settings = Settings::<Unencodable>::load(&path).unwrap().unwrap();
+
+    let mut guard = settings.transaction();
+    guard.value = 5;
+    guard.rollback().unwrap();
+
+    assert_eq!(settings.guard().value, 1);
+    assert_eq!(settings.guard().table.count, 2);
+}
+
+/// Settings that cannot be cloned, which guards must not require.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Handle {
+    count: u32,
+}
+
+#[test]
+fn guards_do_not_require_clone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Handle::default()).unwrap();
+
+    settings.guard_mut().count = 1;
+    settings.lock().unwrap().count += 1;
+    settings.update(|h| h.count += 1).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "count = 3\n");
+
+    let mut guard = settings.guard_mut();
+    guard.count = 10;
+    guard.rollback().unwrap();
+    assert_eq!(*settings.guard(), Handle { count: 3 });
+}
+
+#[test]
+fn rejected_changes_without_a_copy_are_reread() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Handle::default()).unwrap();
+    settings
+        .set_validator(|h: &Handle| {
+            let mut errors = ValidationErrors::new();
+            if h.count > 10 {
+                errors.add("count", "must be at most 10");
+            }
+            errors.into_result()
+        })
+        .unwrap();
+
+    let res = settings.update(|h| h.count = 11);
+
+    assert!(matches!(res, Err(Error::Validation(_))));
+    assert_eq!(*settings.guard(), Handle { count: 0 });
+}
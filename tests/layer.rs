@@ -0,0 +1,204 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{toml, LayeredSettings},
+    std::{fs, path::Path},
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    a: u32,
+    b: u32,
+    c: u32,
+    d: u32,
+    server: Server,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Server {
+    host: String,
+    port: u16,
+}
+
+fn defaults() -> Config {
+    Config {
+        a: 1,
+        b: 1,
+        c: 1,
+        d: 1,
+        server: Server {
+            host: "localhost".into(),
+            port: 80,
+        },
+    }
+}
+
+fn read_toml(path: &Path) -> toml::Value {
+    toml::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn layers_apply_in_priority_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    fs::write(
+        path("system.toml"),
+        "b = 2\nc = 2\nd = 2\n[server]\nport = 8080\n",
+    )
+    .unwrap();
+    fs::write(path("site.toml"), "c = 5\n").unwrap();
+    fs::write(path("user.toml"), "c = 3\nd = 3\n").unwrap();
+    fs::write(
+        path("project.toml"),
+        "d = 4\n[server]\nhost = \"example.com\"\n",
+    )
+    .unwrap();
+
+    let settings = LayeredSettings::new(path("user.toml"))
+        .defaults(defaults())
+        .layer_below(path("system.toml"))
+        .layer_below(path("site.toml"))
+        .layer_below(path("missing.toml"))
+        .layer_above(path("project.toml"))
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        *settings.guard(),
+        Config {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+            server: Server {
+                host: "example.com".into(),
+                port: 8080,
+            },
+        }
+    );
+}
+
+#[test]
+fn later_layers_take_priority() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    fs::write(path("below1.toml"), "a = 2\nb = 2\n").unwrap();
+    fs::write(path("below2.toml"), "a = 3\n").unwrap();
+    fs::write(path("above1.toml"), "c = 2\nd = 2\n").unwrap();
+    fs::write(path("above2.toml"), "d = 3\n").unwrap();
+
+    let settings = LayeredSettings::new(path("user.toml"))
+        .defaults(defaults())
+        .layer_below(path("below1.toml"))
+        .layer_below(path("below2.toml"))
+        .layer_above(path("above1.toml"))
+        .layer_above(path("above2.toml"))
+        .build()
+        .unwrap();
+
+    let c = settings.guard();
+    assert_eq!((c.a, c.b, c.c, c.d), (3, 2, 2, 3));
+}
+
+#[test]
+fn only_differing_values_are_saved() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    fs::write(path("system.toml"), "b = 2\n").unwrap();
+
+    let mut settings = LayeredSettings::new(path("config/user.toml"))
+        .defaults(defaults())
+        .layer_below(path("system.toml"))
+        .build()
+        .unwrap();
+    assert!(!path("config/user.toml").exists());
+
+    settings
+        .update(|c| {
+            c.a = 7;
+            c.b = 2;
+            c.server.port = 8080;
+        })
+        .unwrap();
+
+    assert_eq!(
+        read_toml(&path("config/user.toml")),
+        toml::from_str("a = 7\n[server]\nport = 8080\n").unwrap()
+    );
+}
+
+#[test]
+fn values_kept_in_the_file_are_saved_even_if_inherited() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    fs::write(path("user.toml"), "a = 1\n").unwrap();
+
+    let mut settings = LayeredSettings::new(path("user.toml"))
+        .defaults(defaults())
+        .build()
+        .unwrap();
+    settings.update(|c| c.c = 3).unwrap();
+
+    assert_eq!(
+        read_toml(&path("user.toml")),
+        toml::from_str("a = 1\nc = 3\n").unwrap()
+    );
+}
+
+#[test]
+fn values_from_above_layers_are_not_saved() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    fs::write(path("user.toml"), "d = 3\n").unwrap();
+    fs::write(path("project.toml"), "c = 4\nd = 4\n").unwrap();
+
+    let mut settings = LayeredSettings::new(path("user.toml"))
+        .defaults(defaults())
+        .layer_above(path("project.toml"))
+        .build()
+        .unwrap();
+    assert_eq!((settings.guard().c, settings.guard().d), (4, 4));
+
+    settings.update(|c| c.a = 2).unwrap();
+    // The file keeps its own value underneath the layer above, and does not pick up the inherited one.
+    assert_eq!(
+        read_toml(&path("user.toml")),
+        toml::from_str("a = 2\nd = 3\n").unwrap()
+    );
+
+    settings.update(|c| c.c = 5).unwrap();
+    assert_eq!(
+        read_toml(&path("user.toml")),
+        toml::from_str("a = 2\nc = 5\nd = 3\n").unwrap()
+    );
+    // The layer above still wins when the settings are read again.
+    settings.reload().unwrap();
+    assert_eq!((settings.guard().c, settings.guard().d), (4, 4));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn writable_layer_in_another_format() {
+    use simple_settings::{Format, Json};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    fs::write(path("system.toml"), "b = 2\n").unwrap();
+    fs::write(path("user.json"), r#"{ "c": 3 }"#).unwrap();
+
+    let mut settings = LayeredSettings::new(path("user.json"))
+        .defaults(defaults())
+        .layer_below(path("system.toml"))
+        .build()
+        .unwrap();
+    assert_eq!((settings.guard().b, settings.guard().c), (2, 3));
+
+    settings
+        .update(|c| c.server.host = "example.com".into())
+        .unwrap();
+
+    let saved: toml::Value = Json.decode(&fs::read(path("user.json")).unwrap()).unwrap();
+    assert_eq!(
+        saved,
+        toml::from_str("c = 3\n[server]\nhost = \"example.com\"\n").unwrap()
+    );
+}
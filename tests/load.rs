@@ -0,0 +1,146 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Error, Origin, Settings, ValidationErrors},
+    std::{fs, io},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    count: u32,
+}
+
+#[test]
+fn missing_file_is_none() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    assert!(Settings::<Config>::load(&path).unwrap().is_none());
+}
+
+#[test]
+fn directory_is_io_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::create_dir(&path).unwrap();
+
+    match Settings::<Config>::load(&path) {
+        Err(Error::Io { path: p, source }) => {
+            assert_eq!(p, path);
+            assert_ne!(source.kind(), io::ErrorKind::NotFound);
+        }
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_file_is_io_error() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    Settings::new(&path, Config::default()).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+    if fs::read(&path).is_ok() {
+        // Permissions are not enforced for privileged users.
+        return;
+    }
+
+    match Settings::<Config>::load(&path) {
+        Err(Error::Io { source, .. }) => {
+            assert_eq!(source.kind(), io::ErrorKind::PermissionDenied)
+        }
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn parse_error_has_position_and_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::write(&path, "name = \"test\"\ncount = \"many\"\n").unwrap();
+
+    match Settings::<Config>::load(&path) {
+        Err(Error::Deserialize {
+            path: p,
+            position,
+            key,
+            message,
+        }) => {
+            assert_eq!(p, path);
+            assert_eq!(position.map(|(line, _)| line), Some(2));
+            assert_eq!(key.as_deref(), Some("count"));
+            assert!(!message.is_empty());
+        }
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn validation_error_lists_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+
+    let e = settings
+        .set_validator(|c: &Config| {
+            let mut errors = ValidationErrors::new();
+            if c.name.is_empty() {
+                errors.add("name", "must not be empty");
+            }
+            errors.into_result()
+        })
+        .unwrap_err();
+    match e {
+        Error::Validation(errors) => {
+            let paths: Vec<_> = errors.iter().map(|e| e.path.as_str()).collect();
+            assert_eq!(paths, ["name"]);
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn load_or_else_creates_missing_file_and_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nested/dirs/settings.toml");
+    let init = || Config {
+        name: "init".into(),
+        count: 1,
+    };
+
+    let (mut settings, origin) = Settings::load_or_else(&path, init).unwrap();
+    assert_eq!(origin, Origin::Created);
+    assert_eq!(*settings.guard(), init());
+    assert!(path.is_file());
+
+    settings.update(|c| c.count = 2).unwrap();
+    let (settings, origin) = Settings::<Config>::load_or_else(&path, || unreachable!()).unwrap();
+    assert_eq!(origin, Origin::Loaded);
+    assert_eq!(settings.guard().count, 2);
+}
+
+#[test]
+fn load_or_default_reports_origin() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+
+    let (_, origin) = Settings::<Config>::load_or_default(&path).unwrap();
+    assert_eq!(origin, Origin::Created);
+    let (settings, origin) = Settings::<Config>::load_or_default(&path).unwrap();
+    assert_eq!(origin, Origin::Loaded);
+    assert_eq!(*settings.guard(), Config::default());
+}
+
+#[test]
+fn load_or_else_keeps_unparsable_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::write(&path, "count = \"many\"").unwrap();
+
+    assert!(matches!(
+        Settings::<Config>::load_or_default(&path),
+        Err(Error::Deserialize { .. })
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "count = \"many\"");
+}
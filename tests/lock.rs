@@ -0,0 +1,125 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Error, LockMode, Settings},
+    std::{
+        fs::{self, File},
+        path::Path,
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    a: u32,
+    b: u32,
+}
+
+/// Lock the settings file at `path` the way another process would.
+fn hold_lock(path: &Path) -> File {
+    let lock = path.with_file_name(format!(
+        ".{}.lock",
+        path.file_name().unwrap().to_str().unwrap()
+    ));
+    let file = File::create(lock).unwrap();
+    file.lock().unwrap();
+    file
+}
+
+#[test]
+fn try_fails_while_locked() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.set_lock_mode(LockMode::Try);
+
+    let held = hold_lock(&path);
+    assert!(matches!(
+        settings.update(|c| c.a = 1),
+        Err(Error::Lock { .. })
+    ));
+    assert!(matches!(settings.reload(), Err(Error::Lock { .. })));
+
+    drop(held);
+    settings.update(|c| c.a = 1).unwrap();
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(saved.guard().a, 1);
+}
+
+#[test]
+fn timeout_fails_after_waiting() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    let timeout = Duration::from_millis(100);
+    settings.set_lock_mode(LockMode::Timeout(timeout));
+
+    let _held = hold_lock(&path);
+    let start = Instant::now();
+    assert!(matches!(
+        settings.update(|c| c.a = 1),
+        Err(Error::Lock { .. })
+    ));
+    assert!(start.elapsed() >= timeout);
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(saved.guard().a, 0);
+}
+
+#[test]
+fn block_waits_for_release() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.set_lock_mode(LockMode::Block);
+
+    let held = hold_lock(&path);
+    let release = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(held);
+    });
+    let start = Instant::now();
+    settings.update(|c| c.a = 1).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    release.join().unwrap();
+}
+
+#[test]
+fn update_reloads_changes_made_before_locking() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut first = Settings::new(&path, Config::default()).unwrap();
+    first.set_lock_mode(LockMode::Block);
+    let mut second = Settings::<Config>::load(&path).unwrap().unwrap();
+    second.set_lock_mode(LockMode::Block);
+
+    second.update(|c| c.a = 1).unwrap();
+    first.update(|c| c.b = 2).unwrap();
+
+    assert_eq!(*first.guard(), Config { a: 1, b: 2 });
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(*saved.guard(), Config { a: 1, b: 2 });
+}
+
+#[test]
+fn update_reloads_changes_hidden_from_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    settings.set_lock_mode(LockMode::Block);
+
+    // Edited in place to the same length with the modification time restored,
+    // as on file systems with coarse timestamps.
+    let modified = fs::metadata(&path).unwrap().modified().unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::write(&path, contents.replace("a = 0", "a = 5")).unwrap();
+    let file = File::options().write(true).open(&path).unwrap();
+    file.set_modified(modified).unwrap();
+    drop(file);
+    assert!(!settings.reload_if_changed().unwrap());
+
+    settings.update(|c| c.b = 2).unwrap();
+
+    assert_eq!(*settings.guard(), Config { a: 5, b: 2 });
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(*saved.guard(), Config { a: 5, b: 2 });
+}
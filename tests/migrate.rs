@@ -0,0 +1,93 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Error, Migrations, Settings},
+    std::fs,
+};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    version: u32,
+    name: String,
+    retries: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Unversioned {
+    name: String,
+    retries: u32,
+}
+
+/// Version 2 renames `title` to `name`, version 3 adds `retries`.
+fn migrations() -> Migrations {
+    Migrations::new()
+        .then(|doc| {
+            let table = doc.as_table_mut().ok_or("not a table")?;
+            let title = table.remove("title").ok_or("missing title")?;
+            table.insert("name".into(), title);
+            Ok(())
+        })
+        .then(|doc| {
+            let table = doc.as_table_mut().ok_or("not a table")?;
+            table.insert("retries".into(), 3.into());
+            Ok(())
+        })
+}
+
+#[test]
+fn migrates_v1_to_v3() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let original = "title = \"old\"\n";
+    fs::write(&path, original).unwrap();
+
+    let settings = Settings::<Config>::load_migrated(&path, &migrations())
+        .unwrap()
+        .unwrap();
+    let expected = Config {
+        version: 3,
+        name: "old".into(),
+        retries: 3,
+    };
+    assert_eq!(*settings.guard(), expected);
+
+    let backup = dir.path().join("settings.toml.v1.bak");
+    assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(*saved.guard(), expected);
+
+    // The migrated file is current, so loading it again changes nothing.
+    fs::remove_file(&backup).unwrap();
+    Settings::<Config>::load_migrated(&path, &migrations()).unwrap();
+    assert!(!backup.exists());
+}
+
+#[test]
+fn rejects_newer_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::write(&path, "version = 4\nname = \"new\"\nretries = 1\n").unwrap();
+
+    match Settings::<Config>::load_migrated(&path, &migrations()) {
+        Err(Error::Migration {
+            version: Some(4), ..
+        }) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert!(!dir.path().join("settings.toml.v4.bak").exists());
+}
+
+#[test]
+fn rejects_type_without_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let original = "title = \"old\"\n";
+    fs::write(&path, original).unwrap();
+
+    match Settings::<Unversioned>::load_migrated(&path, &migrations()) {
+        Err(Error::Migration { message, .. }) => {
+            assert!(message.contains("version"), "{}", message)
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), original);
+}
@@ -0,0 +1,135 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Error, Settings},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    a: u32,
+    b: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Optional {
+    name: String,
+    limit: Option<u32>,
+    label: Option<String>,
+    server: Server,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Server {
+    port: u16,
+}
+
+fn override_error(settings: &mut Settings<Optional>, assignment: &str) -> (String, String) {
+    match settings.apply_overrides([assignment]) {
+        Err(Error::Override { key, message }) => (key, message),
+        r => panic!("unexpected result for `{}`: {:?}", assignment, r),
+    }
+}
+
+#[test]
+fn overrides_are_not_saved() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config { a: 1, b: 2 }).unwrap();
+
+    settings.apply_overrides(["a=5"]).unwrap();
+    assert_eq!(*settings.guard(), Config { a: 5, b: 2 });
+    settings.update(|c| c.b = 3).unwrap();
+
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(*saved.guard(), Config { a: 1, b: 3 });
+    settings.reload().unwrap();
+    assert_eq!(*settings.guard(), Config { a: 5, b: 3 });
+}
+
+#[cfg(feature = "bincode")]
+#[test]
+fn env_overrides_round_trip_bincode() {
+    use {simple_settings::EnvOverrides, std::env};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.bincode");
+    let mut settings = Settings::new(&path, Config { a: 1, b: 2 }).unwrap();
+
+    env::set_var("SIMPLE_SETTINGS_ROUND_TRIP_A", "5");
+    settings
+        .apply_env(&EnvOverrides::new("SIMPLE_SETTINGS_ROUND_TRIP"))
+        .unwrap();
+    assert_eq!(*settings.guard(), Config { a: 5, b: 2 });
+    settings.update(|c| c.b = 3).unwrap();
+
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(*saved.guard(), Config { a: 1, b: 3 });
+    settings.reload().unwrap();
+    assert_eq!(*settings.guard(), Config { a: 5, b: 3 });
+}
+
+#[test]
+fn overrides_set_unset_optional_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Optional::default()).unwrap();
+
+    settings
+        .apply_overrides(["limit=5", "label=text", "server.port=8080"])
+        .unwrap();
+    let data = settings.guard();
+    assert_eq!(data.limit, Some(5));
+    assert_eq!(data.label.as_deref(), Some("text"));
+    assert_eq!(data.server.port, 8080);
+}
+
+#[test]
+fn overrides_reject_unknown_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Optional::default()).unwrap();
+
+    let (key, message) = override_error(&mut settings, "missing=1");
+    assert_eq!(key, "missing");
+    assert_eq!(message, "override `missing=1`: unknown key");
+    let (key, message) = override_error(&mut settings, "server.missing=1");
+    assert_eq!(key, "server.missing");
+    assert_eq!(message, "override `server.missing=1`: unknown key");
+    let (key, message) = override_error(&mut settings, "name.first=x");
+    assert_eq!(key, "name.first");
+    assert!(
+        message.contains("`name` is a string, not a table"),
+        "{}",
+        message
+    );
+
+    // Rejected overrides leave the settings unchanged.
+    assert_eq!(*settings.guard(), Optional::default());
+}
+
+#[test]
+fn overrides_reject_mismatched_types() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Optional::default()).unwrap();
+
+    let (key, message) = override_error(&mut settings, "server.port=true");
+    assert_eq!(key, "server.port");
+    assert_eq!(
+        message,
+        "override `server.port=true`: expected an integer, got a boolean `true`"
+    );
+    let (key, message) = override_error(&mut settings, "server.port=70000");
+    assert_eq!(key, "server.port");
+    assert!(message.contains("70000"), "{}", message);
+    let (key, message) = override_error(&mut settings, "limit=many");
+    assert_eq!(key, "limit");
+    assert!(message.contains("invalid type"), "{}", message);
+    let (key, message) = override_error(&mut settings, "server=1");
+    assert_eq!(key, "server");
+    assert!(message.contains("expected a table"), "{}", message);
+
+    let (key, message) = override_error(&mut settings, "limit");
+    assert_eq!(key, "limit");
+    assert!(message.contains("expected `key.path=value`"), "{}", message);
+    assert_eq!(*settings.guard(), Optional::default());
+}
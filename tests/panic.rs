@@ -0,0 +1,144 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Error, Settings, SharedSettings},
+    std::{fs, panic, path::Path},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    a: u32,
+    b: u32,
+}
+
+fn saved(path: &Path) -> Config {
+    let settings = Settings::<Config>::load(path).unwrap().unwrap();
+    let data = settings.guard().clone();
+    data
+}
+
+#[test]
+fn panic_in_guard_discards_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config { a: 1, b: 2 }).unwrap();
+    let before = fs::read(&path).unwrap();
+
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let mut guard = settings.guard_mut();
+        guard.a = 99;
+        panic!("interrupted");
+    }));
+
+    assert!(res.is_err());
+    assert_eq!(fs::read(&path).unwrap(), before);
+    assert_eq!(*settings.guard(), Config { a: 1, b: 2 });
+    settings.update(|c| c.b = 3).unwrap();
+    assert_eq!(saved(&path), Config { a: 1, b: 3 });
+}
+
+#[test]
+fn panic_in_shared_guard_discards_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let shared = SharedSettings::new(Settings::new(&path, Config { a: 1, b: 2 }).unwrap());
+    let before = fs::read(&path).unwrap();
+
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let mut guard = shared.write();
+        guard.a = 99;
+        panic!("interrupted");
+    }));
+
+    assert!(res.is_err());
+    assert_eq!(fs::read(&path).unwrap(), before);
+    assert_eq!(*shared.read(), Config { a: 1, b: 2 });
+    shared.update(|c| c.b = 3).unwrap();
+    assert_eq!(saved(&path), Config { a: 1, b: 3 });
+}
+
+#[test]
+fn unrestorable_panic_poisons_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config { a: 1, b: 2 }).unwrap();
+
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let mut guard = settings.guard_mut();
+        guard.a = 99;
+        // Without a copy of the data the guard reads the file again, which now fails.
+        fs::write(&path, "a = ").unwrap();
+        panic!("interrupted");
+    }));
+
+    assert!(res.is_err());
+    assert!(settings.is_poisoned());
+    assert!(matches!(
+        settings.update(|c| c.b = 3),
+        Err(Error::Poisoned { .. })
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "a = ");
+
+    fs::write(&path, "a = 5\nb = 6\n").unwrap();
+    settings.reload().unwrap();
+    assert!(!settings.is_poisoned());
+    settings.update(|c| c.b = 7).unwrap();
+    assert_eq!(saved(&path), Config { a: 5, b: 7 });
+}
+
+#[test]
+fn cleared_poison_allows_saving() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let shared = SharedSettings::new(Settings::new(&path, Config { a: 1, b: 2 }).unwrap());
+
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let mut guard = shared.write();
+        guard.a = 99;
+        fs::remove_file(&path).unwrap();
+        panic!("interrupted");
+    }));
+
+    assert!(res.is_err());
+    assert!(shared.is_poisoned());
+    assert!(matches!(
+        shared.update(|c| c.b = 3),
+        Err(Error::Poisoned { .. })
+    ));
+    assert!(!path.exists());
+
+    shared.clear_poison();
+    shared.update(|c| c.b = 3).unwrap();
+    assert_eq!(saved(&path), Config { a: 99, b: 3 });
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn panic_in_async_guard_discards_changes() {
+    use simple_settings::AsyncSettings;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let runtime = || {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    };
+    let settings = runtime().block_on(async {
+        let settings = AsyncSettings::new(&path, Config { a: 1, b: 2 })
+            .await
+            .unwrap();
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let mut guard = settings.write();
+            guard.a = 99;
+            panic!("interrupted");
+        }));
+        assert!(res.is_err());
+        settings
+    });
+    // Dropping the runtime waits for saves running in the background.
+
+    assert_eq!(saved(&path), Config { a: 1, b: 2 });
+    assert_eq!(*settings.read(), Config { a: 1, b: 2 });
+    runtime().block_on(settings.update(|c| c.b = 3)).unwrap();
+    assert_eq!(saved(&path), Config { a: 1, b: 3 });
+}
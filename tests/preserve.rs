@@ -0,0 +1,50 @@
+#![cfg(feature = "preserve")]
+
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Backups, Settings},
+    std::fs,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    count: u32,
+}
+
+const ORIGINAL: &str = "# Hand-written settings\nname = \"app\"  # the name\n\ncount = 1\n";
+
+#[test]
+fn unchanged_guard_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::write(&path, ORIGINAL).unwrap();
+    let mut settings = Settings::<Config>::load(&path).unwrap().unwrap();
+    settings.set_backups(Backups::numbered(3));
+    settings.set_preserve_formatting(true);
+
+    drop(settings.guard_mut());
+    settings.update(|_| ()).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), ORIGINAL);
+    assert!(settings.list_backups().unwrap().is_empty());
+}
+
+#[test]
+fn changes_keep_comments() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::write(&path, ORIGINAL).unwrap();
+    let mut settings = Settings::<Config>::load(&path).unwrap().unwrap();
+    settings.set_backups(Backups::numbered(3));
+    settings.set_preserve_formatting(true);
+
+    settings.update(|c| c.count = 2).unwrap();
+    drop(settings.guard_mut());
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        ORIGINAL.replace("count = 1", "count = 2")
+    );
+    assert_eq!(settings.list_backups().unwrap().len(), 1);
+}
@@ -0,0 +1,86 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Backups, Error, Settings},
+    std::{fs, path::Path},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    count: u32,
+}
+
+const CORRUPT: &str = "count = \"not a number\"\n";
+
+/// Names of quarantined copies of the settings file in `dir`.
+fn quarantined(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with("settings.toml.corrupt-"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn recovers_from_newest_parseable_backup() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::write(&path, CORRUPT).unwrap();
+    fs::write(dir.path().join("settings.toml.1"), "count = [\n").unwrap();
+    fs::write(dir.path().join("settings.toml.2"), "count = 2\n").unwrap();
+    fs::write(dir.path().join("settings.toml.3"), "count = 3\n").unwrap();
+
+    let (settings, recovery) =
+        Settings::<Config>::load_or_recover(&path, Backups::numbered(3)).unwrap();
+
+    let recovery = recovery.unwrap();
+    assert!(matches!(recovery.error, Error::Deserialize { .. }));
+    assert_eq!(
+        recovery.restored_from,
+        Some(dir.path().join("settings.toml.2"))
+    );
+    assert_eq!(settings.guard().count, 2);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "count = 2\n");
+    assert_eq!(fs::read_to_string(&recovery.quarantined).unwrap(), CORRUPT);
+    assert_eq!(quarantined(dir.path()).len(), 1);
+}
+
+#[test]
+fn falls_back_to_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    fs::write(&path, CORRUPT).unwrap();
+    fs::write(dir.path().join("settings.toml.1"), "count = [\n").unwrap();
+
+    let (settings, recovery) =
+        Settings::<Config>::load_or_recover(&path, Backups::numbered(3)).unwrap();
+
+    let recovery = recovery.unwrap();
+    assert_eq!(recovery.restored_from, None);
+    assert_eq!(*settings.guard(), Config::default());
+    assert_eq!(
+        Settings::<Config>::load(&path)
+            .unwrap()
+            .unwrap()
+            .guard()
+            .count,
+        0
+    );
+    assert_eq!(fs::read_to_string(&recovery.quarantined).unwrap(), CORRUPT);
+}
+
+#[test]
+fn valid_and_missing_files_need_no_recovery() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+
+    let (_, recovery) = Settings::<Config>::load_or_recover(&path, Backups::numbered(3)).unwrap();
+    assert!(recovery.is_none());
+    fs::write(&path, "count = 7\n").unwrap();
+    let (settings, recovery) =
+        Settings::<Config>::load_or_recover(&path, Backups::numbered(3)).unwrap();
+    assert!(recovery.is_none());
+    assert_eq!(settings.guard().count, 7);
+    assert!(quarantined(dir.path()).is_empty());
+}
@@ -0,0 +1,133 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Error, Settings},
+    std::{
+        fs,
+        path::Path,
+        sync::{Arc, Mutex},
+        thread,
+    },
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    count: u32,
+}
+
+/// Replace the file at `path` with a non-empty directory, so that saving over it fails.
+fn block(path: &Path) {
+    fs::remove_file(path).unwrap();
+    fs::create_dir(path).unwrap();
+    fs::write(path.join("file"), "").unwrap();
+}
+
+#[test]
+fn failed_commit_returns_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    block(&path);
+
+    let mut guard = settings.guard_mut();
+    guard.count = 1;
+    match guard.commit() {
+        Err(Error::Io { path: p, .. }) => assert_eq!(p, path),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    assert!(settings.update(|c| c.count = 2).is_err());
+}
+
+#[test]
+fn failed_save_on_drop_reaches_hook() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let hook_errors = errors.clone();
+    settings.set_error_hook(move |e| hook_errors.lock().unwrap().push(e.to_string()));
+
+    // Unchanged data is not saved, so the hook is not called.
+    drop(settings.guard_mut());
+    block(&path);
+    drop(settings.guard_mut());
+    assert!(errors.lock().unwrap().is_empty());
+
+    settings.guard_mut().count = 1;
+    let errors = errors.lock().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("settings.toml"), "{}", errors[0]);
+}
+
+#[cfg(unix)]
+#[test]
+fn save_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secret.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+    settings.update(|c| c.count = 1).unwrap();
+
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[cfg(unix)]
+#[test]
+fn save_writes_through_symlink() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("real.toml");
+    let link = dir.path().join("link.toml");
+    Settings::new(&target, Config::default()).unwrap();
+    std::os::unix::fs::symlink("real.toml", &link).unwrap();
+
+    let mut settings = Settings::<Config>::load(&link).unwrap().unwrap();
+    settings.update(|c| c.count = 7).unwrap();
+
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    let saved = Settings::<Config>::load(&target).unwrap().unwrap();
+    assert_eq!(saved.guard().count, 7);
+}
+
+#[test]
+fn concurrent_saves_of_one_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    Settings::new(&path, Config::default()).unwrap();
+
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut settings = Settings::<Config>::load(&path).unwrap().unwrap();
+                for n in 0..50 {
+                    // Each thread saves over the others' changes, so conflicts are resolved by reloading.
+                    while let Err(e) = settings.update(|c| {
+                        c.name = format!("thread {}", i);
+                        c.count = n;
+                    }) {
+                        assert!(matches!(e, Error::Conflict { .. }), "{}", e);
+                        settings.reload().unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(saved.guard().count, 49);
+    let leftovers = fs::read_dir(dir.path())
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name() != "settings.toml")
+        .count();
+    assert_eq!(leftovers, 0);
+}
@@ -0,0 +1,72 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Settings, SharedSettings},
+    std::{
+        fs,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    },
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    count: u32,
+}
+
+#[test]
+fn concurrent_commits_write_the_last_change() {
+    const THREADS: u32 = 8;
+    const UPDATES: u32 = 25;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let shared = SharedSettings::new(Settings::new(&path, Config::default()).unwrap());
+    let notified = Arc::new(AtomicUsize::new(0));
+    let counter = notified.clone();
+    shared.subscribe(move |old, new| {
+        assert_ne!(old, new);
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for _ in 0..UPDATES {
+                    shared.update(|c| c.count += 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let total = THREADS * UPDATES;
+    assert_eq!(shared.read().count, total);
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(saved.guard().count, total);
+    assert_eq!(notified.load(Ordering::SeqCst), total as usize);
+}
+
+#[test]
+fn failed_write_is_retried() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let shared = SharedSettings::new(Settings::new(&path, Config::default()).unwrap());
+
+    // A directory in place of the file makes the write fail.
+    fs::remove_file(&path).unwrap();
+    fs::create_dir(&path).unwrap();
+    assert!(shared.update(|c| c.count = 5).is_err());
+    assert_eq!(shared.read().count, 5);
+
+    // Saving the same data again must not be skipped as unchanged.
+    fs::remove_dir(&path).unwrap();
+    shared.write().commit().unwrap();
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(saved.guard().count, 5);
+}
@@ -0,0 +1,154 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Settings, SharedSettings},
+    std::{
+        fs,
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    log: Log,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Log {
+    path: String,
+    level: u32,
+}
+
+/// Changes seen by a subscriber, as `(old, new)` pairs.
+type Seen<V> = Arc<Mutex<Vec<(V, V)>>>;
+
+fn recorder<V, W>(
+    pick: impl Fn(&W) -> V + Send + Sync + 'static,
+) -> (Seen<V>, impl Fn(&W, &W) + Send + Sync + 'static)
+where
+    V: Send + 'static,
+    W: ?Sized,
+{
+    let seen = Seen::default();
+    let record = seen.clone();
+    (seen, move |old: &W, new: &W| {
+        record.lock().unwrap().push((pick(old), pick(new)))
+    })
+}
+
+fn taken<V>(seen: &Seen<V>) -> Vec<(V, V)> {
+    seen.lock().unwrap().drain(..).collect()
+}
+
+#[test]
+fn subscribers_see_old_and_new_data() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    let (seen, callback) = recorder(|c: &Config| c.name.clone());
+    settings.subscribe(callback);
+
+    settings.update(|c| c.name = "first".into()).unwrap();
+    settings.guard_mut().name = "second".into();
+    assert_eq!(
+        taken(&seen),
+        [
+            (String::new(), "first".to_string()),
+            ("first".to_string(), "second".to_string()),
+        ]
+    );
+
+    // Saves that do not change anything and discarded changes are not reported.
+    settings.update(|_| ()).unwrap();
+    let mut guard = settings.guard_mut();
+    guard.name = "discarded".into();
+    guard.rollback().unwrap();
+    assert!(taken(&seen).is_empty());
+    assert_eq!(settings.guard().name, "second");
+}
+
+#[test]
+fn path_subscribers_only_see_their_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    let (seen, callback) = recorder(|c: &Config| c.log.path.clone());
+    settings.subscribe_path("log.path", callback);
+
+    settings.update(|c| c.log.level = 2).unwrap();
+    settings.update(|c| c.name = "app".into()).unwrap();
+    assert!(taken(&seen).is_empty());
+
+    settings.update(|c| c.log.path = "app.log".into()).unwrap();
+    assert_eq!(taken(&seen), [(String::new(), "app.log".to_string())]);
+}
+
+#[test]
+fn field_subscribers_only_see_their_field() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    let (seen, callback) = recorder(|level: &u32| *level);
+    settings.subscribe_field(|c: &Config| &c.log.level, callback);
+    let (seen_str, callback) = recorder(|name: &str| name.to_string());
+    settings.subscribe_field(|c: &Config| c.name.as_str(), callback);
+
+    settings.update(|c| c.log.path = "app.log".into()).unwrap();
+    assert!(taken(&seen).is_empty());
+    assert!(taken(&seen_str).is_empty());
+
+    settings.update(|c| c.log.level = 3).unwrap();
+    settings.update(|c| c.name = "app".into()).unwrap();
+    assert_eq!(taken(&seen), [(0, 3)]);
+    assert_eq!(taken(&seen_str), [(String::new(), "app".to_string())]);
+}
+
+#[test]
+fn unsubscribed_callbacks_are_not_called() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    let (seen, callback) = recorder(|c: &Config| c.log.level);
+    let subscription = settings.subscribe(callback);
+
+    assert!(settings.unsubscribe(subscription));
+    assert!(!settings.unsubscribe(subscription));
+    settings.update(|c| c.log.level = 1).unwrap();
+    assert!(taken(&seen).is_empty());
+}
+
+#[test]
+fn reload_notifies_subscribers() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    let (seen, callback) = recorder(|c: &Config| c.log.path.clone());
+    settings.subscribe_path("log.path", callback);
+
+    settings.reload().unwrap();
+    assert!(taken(&seen).is_empty());
+
+    fs::write(&path, "name = \"\"\n[log]\npath = \"app.log\"\nlevel = 0\n").unwrap();
+    settings.reload().unwrap();
+    assert_eq!(taken(&seen), [(String::new(), "app.log".to_string())]);
+}
+
+#[test]
+fn hot_reload_notifies_shared_subscribers() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let shared = SharedSettings::new(Settings::new(&path, Config::default()).unwrap());
+    let (seen, callback) = recorder(|c: &Config| c.log.path.clone());
+    shared.subscribe_path("log.path", callback);
+    let _reloader = shared.reload_on_change();
+
+    fs::write(&path, "name = \"\"\n[log]\npath = \"app.log\"\nlevel = 0\n").unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while seen.lock().unwrap().is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(taken(&seen), [(String::new(), "app.log".to_string())]);
+}
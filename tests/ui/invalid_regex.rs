@@ -0,0 +1,9 @@
+use simple_settings::Validate;
+
+#[derive(Validate)]
+struct Config {
+    #[setting(regex = "^[a-z+$")]
+    name: String,
+}
+
+fn main() {}
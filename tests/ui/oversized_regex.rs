@@ -0,0 +1,9 @@
+use simple_settings::Validate;
+
+#[derive(Validate)]
+struct Config {
+    #[setting(regex = "^\\w{2000}$")]
+    name: String,
+}
+
+fn main() {}
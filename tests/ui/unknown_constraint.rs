@@ -0,0 +1,9 @@
+use simple_settings::Validate;
+
+#[derive(Validate)]
+struct Config {
+    #[setting(non_emtpy)]
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,154 @@
+use {
+    serde::{Deserialize, Serialize},
+    simple_settings::{Error, Settings, SharedSettings, Watcher},
+    std::{
+        fs,
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant, SystemTime},
+    },
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+    a: u32,
+    b: u32,
+}
+
+/// Wait up to ten seconds for `done` to hold.
+fn eventually(mut done: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if done() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn reload_if_changed_picks_up_edits() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    assert!(!settings.reload_if_changed().unwrap());
+
+    fs::write(&path, "a = 10\nb = 20\n").unwrap();
+    assert!(settings.reload_if_changed().unwrap());
+    assert_eq!(*settings.guard(), Config { a: 10, b: 20 });
+    assert!(!settings.reload_if_changed().unwrap());
+}
+
+#[test]
+fn failed_reload_keeps_data() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config { a: 1, b: 2 }).unwrap();
+
+    fs::write(&path, "a = \"one\"\n").unwrap();
+    assert!(matches!(
+        settings.reload_if_changed(),
+        Err(Error::Deserialize { .. })
+    ));
+    assert!(matches!(settings.reload(), Err(Error::Deserialize { .. })));
+    assert_eq!(*settings.guard(), Config { a: 1, b: 2 });
+
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(settings.reload(), Err(Error::Io { .. })));
+    assert_eq!(*settings.guard(), Config { a: 1, b: 2 });
+}
+
+#[test]
+fn save_fails_after_external_edit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+
+    fs::write(&path, "a = 10\nb = 0\n").unwrap();
+    match settings.update(|c| c.b = 1) {
+        Err(Error::Conflict { path: p }) => assert_eq!(p, path),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), "a = 10\nb = 0\n");
+
+    settings.reload().unwrap();
+    settings.update(|c| c.b = 1).unwrap();
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(*saved.guard(), Config { a: 10, b: 1 });
+}
+
+#[test]
+fn save_ignores_metadata_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+
+    // Touched without changing the contents.
+    let file = fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+    drop(file);
+    settings.update(|c| c.a = 1).unwrap();
+
+    // Deleted files are created again.
+    fs::remove_file(&path).unwrap();
+    settings.update(|c| c.a = 2).unwrap();
+    let saved = Settings::<Config>::load(&path).unwrap().unwrap();
+    assert_eq!(saved.guard().a, 2);
+}
+
+#[test]
+fn polling_watcher_signals_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    Settings::new(&path, Config::default()).unwrap();
+
+    let watcher = Watcher::polling(&path, Duration::from_millis(10));
+    assert!(!watcher.changed());
+    fs::write(&path, "a = 10\nb = 20\n").unwrap();
+    assert!(watcher.wait_timeout(Duration::from_secs(10)));
+    assert!(!watcher.changed());
+}
+
+#[test]
+fn watcher_signals_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+
+    let watcher = settings.watch();
+    let mut other = Settings::<Config>::load(&path).unwrap().unwrap();
+    other.update(|c| c.a = 10).unwrap();
+    assert!(watcher.wait_timeout(Duration::from_secs(10)));
+    assert!(settings.reload_if_changed().unwrap());
+    assert_eq!(settings.guard().a, 10);
+}
+
+#[test]
+fn shared_settings_reload_on_change() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.toml");
+    let mut settings = Settings::new(&path, Config::default()).unwrap();
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let hook_errors = errors.clone();
+    settings.set_error_hook(move |e| hook_errors.lock().unwrap().push(e.to_string()));
+    let shared = SharedSettings::new(settings);
+    let reloader = shared.reload_on_change();
+
+    fs::write(&path, "a = 10\nb = 20\n").unwrap();
+    assert!(eventually(|| shared.read().a == 10));
+    assert_eq!(*shared.read(), Config { a: 10, b: 20 });
+    shared.update(|c| c.b = 30).unwrap();
+
+    // Contents that do not parse are reported and the running configuration is kept.
+    fs::write(&path, "a = \"ten\"\n").unwrap();
+    assert!(eventually(|| !errors.lock().unwrap().is_empty()));
+    assert_eq!(*shared.read(), Config { a: 10, b: 30 });
+    assert!(matches!(
+        shared.update(|c| c.b = 40),
+        Err(Error::Conflict { .. })
+    ));
+
+    drop(reloader);
+    assert!(errors.lock().unwrap()[0].contains("failed to parse"));
+}